@@ -0,0 +1,44 @@
+//! Checking content against the calling agent's blocks, kept in the
+//! `blocklist` zome
+
+use hdk::{
+    api,
+    error::{ZomeApiError, ZomeApiResult},
+    holochain_json_api::{error::JsonError, json::JsonString},
+    holochain_persistence_api::cas::content::Address,
+};
+
+/// A post, comment or vote to check against an agent's blocks
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+pub struct Blockable {
+    pub author: Address,
+    /// Tags the content is in, if it is in any
+    #[serde(default)]
+    pub tags: Vec<u64>,
+    /// Text that blocked keywords are looked for in
+    #[serde(default)]
+    pub texts: Vec<String>,
+}
+
+/// For each of `items`, whether the calling agent has blocked it, from the
+/// `blocklist` zome
+pub fn blocked(items: Vec<Blockable>) -> ZomeApiResult<Vec<bool>> {
+    #[derive(Serialize, Deserialize, Debug, DefaultJson)]
+    struct IsBlockedCallType {
+        items: Vec<Blockable>,
+    }
+    if items.is_empty() {
+        return Ok(Vec::new());
+    }
+    let json_string: String = api::call(
+        hdk::THIS_INSTANCE,
+        "blocklist",
+        Address::from(api::PUBLIC_TOKEN.to_string()),
+        "is_blocked",
+        (IsBlockedCallType { items }).into(),
+    )?
+    .into();
+    serde_json::from_str::<ZomeApiResult<Vec<bool>>>(&json_string)
+        .map_err(|_| ZomeApiError::Internal("Failed to deserialize blocks result".to_owned()))
+        .and_then(|blocked_result| blocked_result)
+}
@@ -0,0 +1,316 @@
+//! Helpers shared by every zome in Comet.
+//!
+//! Zomes cannot call each other's functions directly without going through
+//! `api::call`, so anything that has to run inside validation, or that is
+//! needed by several zomes, lives here instead.
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate chrono;
+
+mod blocks;
+mod properties;
+
+pub use blocks::{blocked, Blockable};
+pub use properties::{CometProperties, CrosspostPolicy};
+
+use hdk::{
+    api,
+    error::{ZomeApiError, ZomeApiResult},
+    holochain_core_types::{
+        chain_header::ChainHeader,
+        entry::Entry,
+        link::LinkMatch,
+        time::{Iso8601, Timeout},
+    },
+    holochain_persistence_api::cas::content::{Address, AddressableContent},
+    holochain_wasm_utils::api_serialization::{
+        get_entry::{GetEntryOptions, GetEntryResultItem, GetEntryResultType, StatusRequestKind},
+        query::{QueryArgsNames, QueryArgsOptions, QueryResult},
+    },
+};
+use serde::de::DeserializeOwned;
+use chrono::DateTime;
+use std::collections::HashSet;
+
+/// How many seconds an entry's `timestamp` may differ from the time in its
+/// chain header
+pub const TIMESTAMP_TOLERANCE: i64 = 60 * 10;
+
+/// Longest URL allowed in posts and profiles, in bytes
+pub const MAX_URL_LENGTH: usize = 2048;
+
+/// Most files that can be attached to a post or comment
+pub const MAX_ATTACHMENTS: usize = 16;
+
+/// Seconds since the unix epoch of a timestamp
+pub fn unix_time(timestamp: &Iso8601) -> Result<i64, String> {
+    DateTime::parse_from_rfc3339(&timestamp.to_string())
+        .map(|time| time.timestamp())
+        .map_err(|_| "Failed to parse timestamp".to_string())
+}
+
+/// Returns `Ok(())` if an entry's `timestamp` is within
+/// `TIMESTAMP_TOLERANCE` of the time in its chain header
+pub fn validate_timestamp(timestamp: &Iso8601, header: &ChainHeader) -> Result<(), String> {
+    if (unix_time(timestamp)? - unix_time(header.timestamp())?).abs() <= TIMESTAMP_TOLERANCE {
+        Ok(())
+    } else {
+        Err("Timestamp is too far from the time of the chain header".to_string())
+    }
+}
+
+/// Options for getting entries along with their chain headers
+pub fn options_with_headers(status_request: StatusRequestKind) -> GetEntryOptions {
+    GetEntryOptions {
+        status_request,
+        entry: true,
+        headers: true,
+        timeout: Timeout::default(),
+    }
+}
+
+/// Turn the result of getting an entry into its address, the entry and its
+/// chain header, if it exists
+fn item_with_header(address: &Address, item: GetEntryResultItem) -> Option<(Address, Entry, ChainHeader)> {
+    let address = item.meta.map(|meta| meta.address).unwrap_or_else(|| address.clone());
+    match (item.entry, item.headers.first()) {
+        (Some(entry), Some(header)) => Some((address, entry, header.clone())),
+        _ => None,
+    }
+}
+
+/// Get the latest version of the entry at `address` along with its address
+/// and chain header
+pub fn get_latest_with_header(address: &Address) -> ZomeApiResult<Option<(Address, Entry, ChainHeader)>> {
+    match api::get_entry_result(address, options_with_headers(StatusRequestKind::Latest))?.result {
+        GetEntryResultType::Single(item) => Ok(item_with_header(address, item)),
+        GetEntryResultType::All(_) => Err(ZomeApiError::Internal(
+            "Getting latest entry returned entry history".to_string(),
+        )),
+    }
+}
+
+/// Get every version of the entry at `address`, oldest first, along with
+/// their addresses and chain headers
+pub fn get_history_with_headers(address: &Address) -> ZomeApiResult<Vec<(Address, Entry, ChainHeader)>> {
+    match api::get_entry_result(address, options_with_headers(StatusRequestKind::All))?.result {
+        GetEntryResultType::All(history) => Ok(history
+            .items
+            .into_iter()
+            .filter_map(|item| item_with_header(address, item))
+            .collect()),
+        GetEntryResultType::Single(item) => Ok(item_with_header(address, item).into_iter().collect()),
+    }
+}
+
+/// Get the address of the first version of the entry at `address`.
+///
+/// Posts and comments are identified by this address: tag, author, comment
+/// and vote links are all made from it, so they stay attached when the entry
+/// is updated.
+pub fn root_address(address: &Address) -> ZomeApiResult<Address> {
+    let mut address = address.clone();
+    loop {
+        match initial_header(&address)?.and_then(|header| header.link_update_delete().clone()) {
+            Some(previous_address) => address = previous_address,
+            None => return Ok(address),
+        }
+    }
+}
+
+/// Chain header of the first version of the entry at `address`, or `None` if
+/// there is no entry there
+pub fn initial_header(address: &Address) -> ZomeApiResult<Option<ChainHeader>> {
+    match api::get_entry_result(address, options_with_headers(StatusRequestKind::Initial))?.result {
+        GetEntryResultType::Single(item) => Ok(item.headers.first().cloned()),
+        GetEntryResultType::All(_) => Err(ZomeApiError::Internal(
+            "Getting initial entry returned entry history".to_string(),
+        )),
+    }
+}
+
+/// Seconds since the unix epoch at which the first version of the entry at
+/// `address` was committed, from its chain header
+pub fn created_at(address: &Address) -> ZomeApiResult<Option<i64>> {
+    match initial_header(address)? {
+        Some(header) => Ok(Some(unix_time(header.timestamp()).map_err(ZomeApiError::Internal)?)),
+        None => Ok(None),
+    }
+}
+
+/// Tag of the link from an agent to a post or comment they made: the entry's
+/// `timestamp` in seconds since the unix epoch. This lets an agent's posts
+/// and comments be sorted by time without getting each of them.
+pub fn author_link_tag(timestamp: &Iso8601) -> Result<String, String> {
+    unix_time(timestamp).map(|time| time.to_string())
+}
+
+/// Get page `page` of the entries linked from `agent` by any of
+/// `link_types`, newest first by the time in their `author_link_tag`. Ties
+/// are broken by address, and links without a time come last. Pages start at
+/// 0 and hold `page_size` entries.
+///
+/// Returns the link type and address of each entry.
+pub fn authored_page(
+    agent: &Address,
+    link_types: &[&str],
+    page: u32,
+    page_size: usize,
+) -> ZomeApiResult<Vec<(String, Address)>> {
+    let mut links: Vec<(i64, String, String)> = Vec::new();
+    for link_type in link_types {
+        for link in api::get_links(agent, LinkMatch::Exactly(link_type), LinkMatch::Any)?.links() {
+            let time = link.tag.parse::<i64>().unwrap_or(i64::min_value());
+            links.push((time, String::from(link.address), link_type.to_string()));
+        }
+    }
+    links.sort_by(|(a_time, a, _), (b_time, b, _)| b_time.cmp(a_time).then_with(|| a.cmp(b)));
+    Ok(links
+        .into_iter()
+        .skip(page as usize * page_size)
+        .take(page_size)
+        .map(|(_, address, link_type)| (link_type, Address::from(address)))
+        .collect())
+}
+
+/// Get the address of the latest version of the entry at `address`, or
+/// `None` if there is no entry there
+pub fn latest_address(address: &Address) -> ZomeApiResult<Option<Address>> {
+    let options = GetEntryOptions {
+        status_request: StatusRequestKind::Latest,
+        entry: false,
+        headers: false,
+        timeout: Timeout::default(),
+    };
+    match api::get_entry_result(address, options)?.result {
+        GetEntryResultType::Single(item) => Ok(item.meta.map(|meta| meta.address)),
+        GetEntryResultType::All(_) => Err(ZomeApiError::Internal(
+            "Getting latest entry returned entry history".to_string(),
+        )),
+    }
+}
+
+/// Entries of type `entry_type` in this agent's source chain, newest first,
+/// along with their chain headers. Entries that aren't a `T` are skipped.
+///
+/// The first entry for a key is the latest one, even when several were
+/// committed within the same second.
+pub fn newest_chain_entries<T: DeserializeOwned>(entry_type: &str) -> ZomeApiResult<Vec<(ChainHeader, T)>> {
+    let entries = match api::query_result(
+        QueryArgsNames::QueryName(entry_type.to_string()),
+        QueryArgsOptions {
+            start: 0,
+            limit: usize::max_value(),
+            headers: true,
+            entries: true,
+        },
+    )? {
+        QueryResult::HeadersWithEntries(entries) => entries,
+        _ => unreachable!(),
+    };
+    Ok(entries
+        .into_iter()
+        .filter_map(|(header, entry)| match entry {
+            Entry::App(_, value) => serde_json::from_str::<T>(&Into::<String>::into(value))
+                .ok()
+                .map(|content| (header, content)),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Entries of type `entry_type` in the source chain entries of a validation
+/// package (from `ValidationPackageDefinition::ChainEntries`), along with
+/// their addresses. Entries that were deleted in the same chain are left out.
+///
+/// Everything comes from the package sent by the author, so every validator
+/// gets the same result no matter what it has seen of the DHT.
+pub fn live_chain_entries<T: DeserializeOwned>(
+    source_chain_entries: &Option<Vec<Entry>>,
+    entry_type: &str,
+) -> Result<Vec<(Address, T)>, String> {
+    let entries = source_chain_entries
+        .as_ref()
+        .ok_or_else(|| "Internal error: Invalid validation package.".to_owned())?;
+    let deleted: HashSet<Address> = entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::Deletion(deletion) => Some(deletion.deleted_entry_address().clone()),
+            _ => None,
+        })
+        .collect();
+    Ok(entries
+        .iter()
+        .filter_map(|entry| match entry {
+            Entry::App(app_type, value) if Into::<String>::into(app_type.clone()) == entry_type => {
+                let address = entry.address();
+                if deleted.contains(&address) {
+                    None
+                } else {
+                    serde_json::from_str::<T>(&Into::<String>::into(value.clone()))
+                        .ok()
+                        .map(|content| (address, content))
+                }
+            }
+            _ => None,
+        })
+        .collect())
+}
+
+/// Returns `Ok(())` if every attachment is the address of a file manifest
+pub fn validate_attachments(attachments: &[Address]) -> Result<(), String> {
+    if attachments.len() > MAX_ATTACHMENTS {
+        return Err(format!("At most {} files can be attached", MAX_ATTACHMENTS));
+    }
+    for attachment in attachments {
+        match api::get_entry(attachment) {
+            Ok(Some(Entry::App(entry_type, _))) if Into::<String>::into(entry_type.clone()) == "manifest" => (),
+            _ => return Err("Attachments must be file manifests".to_string()),
+        }
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if `address` is the address of the manifest of an image
+/// uploaded with the `files` zome. `what` names the field in errors.
+pub fn validate_image_manifest(address: &Address, what: &str) -> Result<(), String> {
+    match api::get_entry(address) {
+        Ok(Some(Entry::App(entry_type, value))) if Into::<String>::into(entry_type.clone()) == "manifest" => {
+            let manifest = serde_json::from_str::<serde_json::Value>(&Into::<String>::into(value))
+                .map_err(|_| format!("{} must be a file manifest", what))?;
+            if manifest["mime_type"].as_str().map_or(false, |mime_type| mime_type.starts_with("image/")) {
+                Ok(())
+            } else {
+                Err(format!("{} must be an image", what))
+            }
+        }
+        _ => Err(format!("{} must be a file manifest", what)),
+    }
+}
+
+/// Returns `Ok(())` if `url` looks like an absolute http(s) URL
+pub fn validate_url(url: &str) -> Result<(), String> {
+    if url.len() > MAX_URL_LENGTH {
+        return Err(format!("URL must be at most {} bytes", MAX_URL_LENGTH));
+    }
+    let rest = if url.starts_with("https://") {
+        &url[8..]
+    } else if url.starts_with("http://") {
+        &url[7..]
+    } else {
+        return Err("URL must start with http:// or https://".to_string());
+    };
+    let host = rest.split(|c: char| c == '/' || c == '?' || c == '#').next().unwrap_or("");
+    if host.is_empty() {
+        Err("URL must have a host".to_string())
+    } else if url.chars().any(|c| c.is_whitespace() || c.is_control()) {
+        Err("URL cannot contain whitespace".to_string())
+    } else {
+        Ok(())
+    }
+}
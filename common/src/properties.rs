@@ -0,0 +1,104 @@
+//! Network-wide configuration, read from the DNA properties so that forks of
+//! the network can change it without changing code
+
+use hdk::{
+    api,
+    holochain_json_api::{error::JsonError, json::JsonString},
+};
+use serde::de::DeserializeOwned;
+use std::collections::HashMap;
+
+/// Every DNA property Comet reads. Properties that are not set take their
+/// default; properties that are set but malformed are an error.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+pub struct CometProperties {
+    /// Longest post title, in characters
+    pub max_title_length: usize,
+    /// Longest post content, in characters
+    pub max_post_length: usize,
+    /// Longest comment, in characters
+    pub max_comment_length: usize,
+    /// Kinds of posts that can be created, by their `type`
+    pub allowed_post_kinds: Vec<String>,
+    /// Most original tags a post can have
+    pub max_tags_per_post: usize,
+    /// Most tags a single agent can crosspost a post to
+    pub max_crosspost_count: usize,
+    /// Most crossposts an agent can make in an hour
+    pub max_crossposts_per_hour: usize,
+    /// Who can crosspost into each tag. Tags that are not listed are
+    /// `CrosspostPolicy::Open`.
+    pub crosspost_policies: HashMap<u64, CrosspostPolicy>,
+    /// Longest chain of delegations that is followed when scoring
+    pub max_delegation_depth: usize,
+    /// Most parents that are followed from a comment to find the post it
+    /// was made on
+    pub max_comment_depth: usize,
+}
+
+/// Who can crosspost posts into a tag
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum CrosspostPolicy {
+    /// Anybody can crosspost any post into the tag
+    Open,
+    /// Only the author of a post can crosspost it into the tag
+    AuthorOnly,
+    /// Posts cannot be crossposted into the tag
+    Closed,
+}
+
+impl Default for CometProperties {
+    fn default() -> Self {
+        CometProperties {
+            max_title_length: 300,
+            max_post_length: 40_000,
+            max_comment_length: 10_000,
+            allowed_post_kinds: ["text", "link", "image", "file", "poll"]
+                .iter()
+                .map(|kind| kind.to_string())
+                .collect(),
+            max_tags_per_post: 8,
+            max_crosspost_count: 16,
+            max_crossposts_per_hour: 30,
+            crosspost_policies: HashMap::new(),
+            max_delegation_depth: 5,
+            max_comment_depth: 256,
+        }
+    }
+}
+
+impl CometProperties {
+    /// Read the DNA properties, using the default for any that are not set
+    pub fn load() -> Result<Self, String> {
+        let default = CometProperties::default();
+        Ok(CometProperties {
+            max_title_length: property_or("max_title_length", default.max_title_length)?,
+            max_post_length: property_or("max_post_length", default.max_post_length)?,
+            max_comment_length: property_or("max_comment_length", default.max_comment_length)?,
+            allowed_post_kinds: property_or("allowed_post_kinds", default.allowed_post_kinds)?,
+            max_tags_per_post: property_or("max_tags_per_post", default.max_tags_per_post)?,
+            max_crosspost_count: property_or("max_crosspost_count", default.max_crosspost_count)?,
+            max_crossposts_per_hour: property_or("max_crossposts_per_hour", default.max_crossposts_per_hour)?,
+            crosspost_policies: property_or("crosspost_policies", default.crosspost_policies)?,
+            max_delegation_depth: property_or("max_delegation_depth", default.max_delegation_depth)?,
+            max_comment_depth: property_or("max_comment_depth", default.max_comment_depth)?,
+        })
+    }
+}
+
+/// Get the DNA property `name`, or `default` if it is not set (or `null`).
+///
+/// A property that is set to a value of the wrong type is an error rather
+/// than falling back to `default`, so that a misconfigured network fails
+/// loudly instead of quietly running with other limits.
+fn property_or<T: DeserializeOwned>(name: &str, default: T) -> Result<T, String> {
+    let value = match api::property(name) {
+        Ok(value) => String::from(value),
+        Err(_) => return Ok(default),
+    };
+    if value == "null" {
+        return Ok(default);
+    }
+    serde_json::from_str(&value).map_err(|_| format!("DNA property `{}` is malformed", name))
+}
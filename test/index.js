@@ -1,37 +1,87 @@
 // This test file uses the tape testing framework.
 // To learn more, go here: https://github.com/substack/tape
 const { Diorama, tapeExecutor } = require('@holochain/diorama');
+const crypto = require('crypto');
+const fs = require('fs');
 const path = require('path');
 
-const dnaPath = path.join(__dirname, '../dist/Comet.dna.json');
+// Tests run on a copy of the DNA with properties that only tests need, so
+// that `app.json` keeps every property at its default
+const testProperties = {
+    crosspost_policies: { 100: 'author_only', 101: 'closed' },
+};
+const dnaPath = path.join(__dirname, '../dist/Comet.test.dna.json');
+fs.writeFileSync(dnaPath, JSON.stringify({
+    ...JSON.parse(fs.readFileSync(path.join(__dirname, '../dist/Comet.dna.json'))),
+    properties: testProperties,
+}));
 const dna = Diorama.dna(dnaPath, 'comet');
 const diorama = new Diorama({
     instances: {
-        alice: dna
+        alice: dna,
+        bob: dna,
     },
     debugLog: false,
     executor: tapeExecutor(require('tape')),
 });
 
 // Constants for testing
+const isoTime = (time) => new Date(time * 1000).toISOString().replace('.000Z', '+00:00');
 const testAnchor = { anchor_type: 'type', anchor_text: 'text' };
 let anchorAddress;
 let testPost;
 
-diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
+// Entry timestamps must be close to the time of their chain header, so
+// scenarios take the current time when they start
+const currentTime = () => Math.floor(Date.now() / 1000);
+
+const base58Alphabet = '123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz';
+const base58 = (bytes) => {
+    const digits = [0];
+    for (const byte of bytes) {
+        let carry = byte;
+        for (let i = 0; i < digits.length; i++) {
+            carry += digits[i] << 8;
+            digits[i] = carry % 58;
+            carry = (carry / 58) | 0;
+        }
+        while (carry > 0) {
+            digits.push(carry % 58);
+            carry = (carry / 58) | 0;
+        }
+    }
+    let leadingZeros = '';
+    for (let i = 0; i < bytes.length && bytes[i] === 0; i++) {
+        leadingZeros += base58Alphabet[0];
+    }
+    return leadingZeros + digits.reverse().map(digit => base58Alphabet[digit]).join('');
+};
+
+// Address Holochain gives an entry: the base58 SHA2-256 multihash of the
+// entry serialized as JSON. Fields of `content` must be in the same order as
+// in the zome's struct.
+const addressOf = (entry) => {
+    const digest = crypto.createHash('sha256').update(JSON.stringify(entry)).digest();
+    return base58(Buffer.concat([Buffer.from([0x12, 0x20]), digest]));
+};
+const entryAddress = (entryType, content) => addressOf({ App: [entryType, JSON.stringify(content)] });
+const deletionAddress = (deletedAddress) => addressOf({ Deletion: { deleted_entry_address: deletedAddress } });
+
+diorama.registerScenario('Test votes zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
     const postAddress = await alice.call('posts', 'create_post', {
         post: {
             title: 'Testing post',
             content: 'This post is used for testing the votes zome',
-            utc_unix_time: 0,
+            utc_unix_time: now,
         },
-        tags: [0],
+        tags: [0, 1],
     });
 
     const commentAddress = await alice.call('comments', 'create_comment', {
         comment: {
             content: 'This comment is used for testing the votes zome',
-            utc_unix_time: 0,
+            utc_unix_time: now,
         },
         target: postAddress.Ok,
     });
@@ -44,6 +94,7 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
     t.deepEquals(
         await alice.call('votes', 'votes_from_address', {
             address: postAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [] },
         'Getting votes of unvoted on post returns the empty list',
@@ -52,6 +103,7 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
     t.deepEquals(
         await alice.call('votes', 'votes_from_address', {
             address: commentAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [] },
         'Getting votes of unvoted on comment returns the empty list',
@@ -64,7 +116,7 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         JSON.parse((await alice.call('votes', 'vote', {
             fraction: 1000,
             in_terms_of: [1],
-            utc_unix_time: 0,
+            utc_unix_time: now,
             target: postAddress.Ok,
         })).Err.Internal).kind,
         { ValidationFailed: 'Vote fraction must be between 1 and -1' },
@@ -76,18 +128,42 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         JSON.parse((await alice.call('votes', 'vote', {
             fraction: 1000,
             in_terms_of: [1],
-            utc_unix_time: 0,
+            utc_unix_time: now,
             target: commentAddress.Ok,
         })).Err.Internal).kind,
         { ValidationFailed: 'Vote fraction must be between 1 and -1' },
         'Cannot vote on comment with invalid fraction',
     );
+    // NEGATIVE - Invalid tags //
+    // ON POST //
+    t.deepEquals(
+        await alice.call('votes', 'vote', {
+            fraction: 1,
+            in_terms_of: [7],
+            utc_unix_time: now,
+            target: postAddress.Ok,
+        }),
+        { Err: { Internal: 'Vote must be in terms of the tags of its target' } },
+        'Cannot vote on post in terms of tags it is not in',
+    );
+
+    // ON COMMENT //
+    t.deepEquals(
+        await alice.call('votes', 'vote', {
+            fraction: 1,
+            in_terms_of: [7],
+            utc_unix_time: now,
+            target: commentAddress.Ok,
+        }),
+        { Err: { Internal: 'Vote must be in terms of the tags of its target' } },
+        'Cannot vote on comment in terms of tags its post is not in',
+    );
     // NEGATIVE - Invalid target //
     t.deepEquals(
         (await alice.call('votes', 'vote', {
             fraction: 1,
             in_terms_of: [1],
-            utc_unix_time: 0,
+            utc_unix_time: now,
             target: "invalid",
         })).Err.Internal,
         'Vote target was not app entry.',
@@ -100,10 +176,18 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         await alice.call('votes', 'vote', {
             fraction: 1,
             in_terms_of: [1],
-            utc_unix_time: 0,
+            utc_unix_time: now,
             target: postAddress.Ok,
         }),
-        { Ok: 'Qmedhx72FQQZorSNZpNxUBoiYttmKqdakyT9vCS2h8pXFS' },
+        {
+            Ok: entryAddress('vote', {
+                fraction: 1000,
+                in_terms_of: [1],
+                target_hash: postAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now),
+            }),
+        },
         'Can vote on posts',
     );
 
@@ -112,10 +196,18 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         await alice.call('votes', 'vote', {
             fraction: 1,
             in_terms_of: [1],
-            utc_unix_time: 0,
+            utc_unix_time: now,
             target: commentAddress.Ok,
         }),
-        { Ok: 'QmSXAFbsqUEyjABf1H1x1CvfM4eAyVwS1AX1d1w9nKMypY' },
+        {
+            Ok: entryAddress('vote', {
+                fraction: 1000,
+                in_terms_of: [1],
+                target_hash: commentAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now),
+            }),
+        },
         'Can vote on comments',
     );
 
@@ -128,10 +220,18 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         await alice.call('votes', 'vote', {
             fraction: 0.5,
             in_terms_of: [1],
-            utc_unix_time: 1,
+            utc_unix_time: now + 1,
             target: postAddress.Ok,
         }),
-        { Ok: 'QmR1WyyzsELNnftDUAc5DH9GASfLnVWFNN1wH2CCirupqY' },
+        {
+            Ok: entryAddress('vote', {
+                fraction: 500,
+                in_terms_of: [1],
+                target_hash: postAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now + 1),
+            }),
+        },
         'Can revote on posts',
     );
 
@@ -140,10 +240,18 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         await alice.call('votes', 'vote', {
             fraction: 0.5,
             in_terms_of: [1],
-            utc_unix_time: 1,
+            utc_unix_time: now + 1,
             target: commentAddress.Ok,
         }),
-        { Ok: 'QmddykFErZ3Qs86Afj2TLZjDm6RFVmYrfRMiineDgp4qUo' },
+        {
+            Ok: entryAddress('vote', {
+                fraction: 500,
+                in_terms_of: [1],
+                target_hash: commentAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now + 1),
+            }),
+        },
         'Can revote on comments',
     );
 
@@ -153,7 +261,7 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         JSON.parse((await alice.call('votes', 'vote', {
             fraction: 5,
             in_terms_of: [1],
-            utc_unix_time: 1,
+            utc_unix_time: now + 1,
             target: postAddress.Ok,
         })).Err.Internal).kind,
         { ValidationFailed: 'Vote fraction must be between 1 and -1' },
@@ -165,7 +273,7 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
         JSON.parse((await alice.call('votes', 'vote', {
             fraction: 5,
             in_terms_of: [1],
-            utc_unix_time: 1,
+            utc_unix_time: now + 1,
             target: commentAddress.Ok,
         })).Err.Internal).kind,
         { ValidationFailed: 'Vote fraction must be between 1 and -1' },
@@ -180,14 +288,15 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
     t.deepEquals(
         await alice.call('votes', 'votes_from_address', {
             address: postAddress.Ok,
+            apply_filters: false,
         }),
         {
             Ok: [{
-                fraction: 0.5,
+                fraction: 500,
                 in_terms_of: [1],
                 target_hash: postAddress.Ok,
                 key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-                timestamp: '1970-01-01T00:00:01+00:00',
+                timestamp: isoTime(now + 1),
             }]
         },
         'Can get votes from post',
@@ -197,18 +306,247 @@ diorama.registerScenario('Test votes zome', async (s, t, { alice }) => {
     t.deepEquals(
         await alice.call('votes', 'votes_from_address', {
             address: commentAddress.Ok,
+            apply_filters: false,
         }),
         {
             Ok: [{
-                fraction: 0.5,
+                fraction: 500,
                 in_terms_of: [1],
                 target_hash: commentAddress.Ok,
                 key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-                timestamp: '1970-01-01T00:00:01+00:00',
+                timestamp: isoTime(now + 1),
             }]
         },
         'Can get votes from post',
     );
+
+    /// SCORING ///
+    // POSITIVE //
+    t.deepEquals(
+        await alice.call('votes', 'score', {
+            address: postAddress.Ok,
+            in_terms_of: [1],
+            utc_unix_time: now,
+            half_life: null,
+        }),
+        { Ok: { score: 500, votes: 1 } },
+        'Can score posts without decay',
+    );
+
+    await (async () => {
+        const fresh = await alice.call('votes', 'score', {
+            address: commentAddress.Ok,
+            in_terms_of: [1],
+            utc_unix_time: now,
+            half_life: 60 * 60 * 24,
+        });
+        t.ok(
+            fresh.Ok && fresh.Ok.votes == 1 && fresh.Ok.score > 450 && fresh.Ok.score <= 500,
+            'Recent votes barely decay',
+        );
+        const stale = await alice.call('votes', 'score', {
+            address: commentAddress.Ok,
+            in_terms_of: [1],
+            utc_unix_time: now + 60 * 60 * 24 * 365,
+            half_life: 60 * 60 * 24,
+        });
+        t.deepEquals(stale, { Ok: { score: 0, votes: 1 } }, 'Old votes decay completely');
+    })();
+
+    // NEGATIVE //
+    t.deepEquals(
+        await alice.call('votes', 'score', {
+            address: postAddress.Ok,
+            in_terms_of: [0],
+            utc_unix_time: now,
+            half_life: null,
+        }),
+        { Ok: { score: 0, votes: 0 } },
+        'Votes in terms of other tags are not scored',
+    );
+
+    await (async () => {
+        const timedVotes = await alice.call('votes', 'timed_votes_from_address', {
+            address: postAddress.Ok,
+        });
+        t.ok(
+            timedVotes.Ok &&
+            timedVotes.Ok.length == 1 &&
+            timedVotes.Ok[0].vote.fraction == 500 &&
+            timedVotes.Ok[0].header_timestamp != timedVotes.Ok[0].vote.timestamp,
+            'Timed votes use header timestamps',
+        );
+    })();
+
+    /// DELEGATION ///
+    const aliceAddress = await alice.call('posts', 'get_agent_address', {});
+    const bobAddress = await bob.call('posts', 'get_agent_address', {});
+
+    // NEGATIVE //
+    t.deepEquals(
+        JSON.parse((await bob.call('votes', 'delegate', {
+            utc_unix_time: now,
+            tag: 1,
+            agent: bobAddress,
+            weight: 1,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Cannot delegate to yourself' },
+        'Cannot delegate to yourself',
+    );
+
+    t.deepEquals(
+        JSON.parse((await bob.call('votes', 'delegate', {
+            utc_unix_time: now,
+            tag: 1,
+            agent: aliceAddress,
+            weight: 2,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Delegation weight must be between 0 and 1' },
+        'Cannot delegate more than your own weight',
+    );
+
+    t.deepEquals(
+        await bob.call('votes', 'revoke_delegation', { tag: 1 }),
+        { Err: { Internal: 'No delegation in that tag' } },
+        'Cannot revoke delegation that was not made',
+    );
+
+    // POSITIVE //
+    const bobDelegation = await bob.call('votes', 'delegate', {
+        utc_unix_time: now,
+        tag: 1,
+        agent: aliceAddress,
+        weight: 0.5,
+    });
+    t.ok(bobDelegation.Ok, 'Can delegate voting weight');
+
+    t.deepEquals(
+        await bob.call('votes', 'delegate', {
+            utc_unix_time: now,
+            tag: 2,
+            agent: aliceAddress,
+            weight: 0.6,
+        }),
+        { Err: { Internal: 'Cannot delegate more than your full weight in total' } },
+        'Cannot delegate more than your full weight across tags',
+    );
+
+    // Delegating back to a voter creates a cycle, which must not be counted
+    t.ok(
+        (await alice.call('votes', 'delegate', {
+            utc_unix_time: now,
+            tag: 1,
+            agent: bobAddress,
+            weight: 1,
+        })).Ok,
+        'Can delegate voting weight to a delegator',
+    );
+
+    await s.consistent();
+
+    t.deepEquals(
+        await alice.call('votes', 'delegations_to', { agent: aliceAddress }),
+        {
+            Ok: [{
+                tag: 1,
+                delegate: aliceAddress,
+                weight: 500,
+                key_hash: bobAddress,
+                timestamp: isoTime(now),
+            }]
+        },
+        'Can get delegations made to an agent',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('votes', 'revoke_delegation_raw', {
+            address: bobDelegation.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Delegations can only be linked and unlinked by their delegator' },
+        'Cannot remove the links of a delegation made by another agent',
+    );
+    await s.consistent();
+
+    t.deepEquals(
+        await alice.call('votes', 'score', {
+            address: postAddress.Ok,
+            in_terms_of: [1],
+            utc_unix_time: now,
+            half_life: null,
+        }),
+        { Ok: { score: 750, votes: 1 } },
+        'Delegated weight is counted when scoring',
+    );
+
+    t.ok(
+        (await bob.call('votes', 'revoke_delegation', { tag: 1 })).Ok,
+        'Can revoke delegations',
+    );
+
+    await s.consistent();
+
+    t.deepEquals(
+        await alice.call('votes', 'score', {
+            address: postAddress.Ok,
+            in_terms_of: [1],
+            utc_unix_time: now,
+            half_life: null,
+        }),
+        { Ok: { score: 500, votes: 1 } },
+        'Revoked delegations are not counted when scoring',
+    );
+
+    /// VOTING ///
+    // NEGATIVE - Mismatched target //
+    t.deepEquals(
+        JSON.parse((await alice.call('votes', 'vote_raw', {
+            vote: {
+                fraction: 1000,
+                in_terms_of: [1],
+                target_hash: commentAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now + 2),
+            },
+            target: postAddress.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Vote must be linked from its target' },
+        'Cannot link vote from anything but its target',
+    );
+
+    // NEGATIVE - Tags the target is not in //
+    t.ok(
+        (await alice.call('votes', 'vote_raw', {
+            vote: {
+                fraction: 1000,
+                in_terms_of: [7],
+                target_hash: postAddress.Ok,
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now + 3),
+            },
+            target: postAddress.Ok,
+        })).Ok,
+        'Votes in terms of tags their target is not in can be committed directly',
+    );
+    await s.consistent();
+
+    t.ok(
+        !(await bob.call('votes', 'votes_from_address', {
+            address: postAddress.Ok,
+            apply_filters: false,
+        })).Ok.some(vote => vote.in_terms_of.includes(7)),
+        'Votes in terms of tags their target is not in are left out',
+    );
+
+    t.deepEquals(
+        await bob.call('votes', 'score', {
+            address: postAddress.Ok,
+            in_terms_of: [7],
+            utc_unix_time: now,
+            half_life: null,
+        }),
+        { Ok: { score: 0, votes: 0 } },
+        'Votes in terms of tags their target is not in are not scored',
+    );
 });
 
 diorama.registerScenario('Test anchors zome', async (s, t, { alice }) => {
@@ -257,11 +595,12 @@ diorama.registerScenario('Test anchors zome', async (s, t, { alice }) => {
     );
 });
 
-diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
+diorama.registerScenario('Test posts zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
     const testPostEntry = {
         title: 'This is a test post',
         content: 'This is the content of the post',
-        utc_unix_time: 0,
+        utc_unix_time: now,
     };
 
     /// GET USERNAME ///
@@ -287,7 +626,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
     // NEGATIVE //
     t.deepEquals(
         await alice.call('posts', 'user_posts', {
-            author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui'
+            author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            apply_filters: false,
         }),
         { Ok: [] },
         'User has no posts attributed to them'
@@ -300,7 +640,18 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         tags: [1, 2],
     });
 
-    t.deepEquals(testPost, { Ok: 'QmZznbVvtoWZ3PwsEKqJyRYRWpsW6kU5wpFsHWfCmX8xLk' }, 'Address is correct');
+    t.deepEquals(
+        testPost,
+        {
+            Ok: entryAddress('post', {
+                title: 'This is a test post',
+                content: 'This is the content of the post',
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now),
+            }),
+        },
+        'Posts can be created',
+    );
 
     // NEGATIVE //
     const invalidTestPostEntry = {
@@ -329,13 +680,74 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         }
     })();
 
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post_raw', {
+            post: { ...invalidTestPostEntry, key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui' },
+            tags: [1, 2],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Timestamp is too far from the time of the chain header' },
+        'Cannot create post with timestamp far from header time',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post_raw', {
+            post: {
+                title: '[deleted]',
+                content: '[deleted]',
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now),
+                deleted: true,
+            },
+            tags: [1],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Posts cannot be created deleted.' },
+        'Cannot create post that is already deleted',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: '   ' },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Post title cannot be empty' },
+        'Cannot create post with empty title',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: 'x'.repeat(301) },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Post title must be at most 300 characters' },
+        'Cannot create post with title longer than the DNA allows',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: 'Line\nbreak' },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Post title cannot contain control characters' },
+        'Cannot create post with control characters in title',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, content: 'x'.repeat(40001) },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Post content must be at most 40000 characters' },
+        'Cannot create post with content longer than the DNA allows',
+    );
+
     await s.consistent();
 
     /// USER POSTS ///
     // POSITIVE //
     t.deepEquals(
         await alice.call('posts', 'user_posts', {
-            author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui'
+            author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            apply_filters: false,
         }),
         { Ok: [testPost.Ok] },
         'Author has posts attributed to them'
@@ -367,6 +779,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "exactly", values: 1 },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [{ address: testPost.Ok, in_terms_of: [1] }] },
         'Exactly query finds post'
@@ -376,6 +790,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "exactly", values: 5 },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Exactly query finds no post'
@@ -385,6 +801,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "or", values: [{ type: "exactly", values: 5 }, { type: "exactly", values: 1 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [{ address: testPost.Ok, in_terms_of: [1] }] },
         'Or query finds post'
@@ -394,6 +812,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "or", values: [{ type: "exactly", values: 5 }, { type: "exactly", values: 8 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Or query finds no post'
@@ -403,6 +823,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "and", values: [{ type: "exactly", values: 1 }, { type: "exactly", values: 2 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [{ address: testPost.Ok, in_terms_of: [2, 1] }] },
         'And query finds post'
@@ -412,6 +834,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "and", values: [{ type: "exactly", values: 0 }, { type: "exactly", values: 3 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'And query finds no post'
@@ -421,6 +845,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "not", values: [{ type: "exactly", values: 2 }, { type: "exactly", values: 5 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [{ address: testPost.Ok, in_terms_of: [2] }] },
         'Not query finds post'
@@ -430,6 +856,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "not", values: [{ type: "exactly", values: 1 }, { type: "exactly", values: 2 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Not query finds no post'
@@ -439,6 +867,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "xor", values: [{ type: "exactly", values: 5 }, { type: "exactly", values: 2 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [{ address: testPost.Ok, in_terms_of: [2] }] },
         'Xor query finds post'
@@ -448,6 +878,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "xor", values: [{ type: "exactly", values: 1 }, { type: "exactly", values: 2 }] },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Xor query finds no post'
@@ -479,6 +911,64 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         'Cannot crosspost invalid target'
     );
 
+    await (async () => {
+        const notAPost = await alice.call('anchors', 'anchor', {
+            anchor: { anchor_type: 'tag', anchor_text: '99' },
+        });
+        t.deepEqual(
+            JSON.parse((await alice.call('posts', 'crosspost', {
+                post_address: notAPost.Ok,
+                tags: [3],
+            })).Err.Internal).kind,
+            { ValidationFailed: 'Crossposts must be of posts.' },
+            'Cannot crosspost entries that are not posts',
+        );
+
+        const notATag = await alice.call('anchors', 'anchor', {
+            anchor: { anchor_type: 'type', anchor_text: '3' },
+        });
+        t.deepEqual(
+            JSON.parse((await alice.call('posts', 'crosspost_raw', {
+                post_address: testPost.Ok,
+                anchor_address: notATag.Ok,
+            })).Err.Internal).kind,
+            { ValidationFailed: 'Link must be to a tag anchor.' },
+            'Cannot crosspost to anchors that are not tags',
+        );
+    })();
+
+    await (async () => {
+        const policyPost = await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: 'Crossposted by policy' },
+            tags: [0],
+        });
+        await s.consistent();
+
+        t.deepEqual(
+            await alice.call('posts', 'crosspost', { post_address: policyPost.Ok, tags: [100] }),
+            { Ok: null },
+            'Authors can crosspost their posts into author only tags',
+        );
+
+        t.deepEqual(
+            JSON.parse((await bob.call('posts', 'crosspost', {
+                post_address: policyPost.Ok,
+                tags: [100],
+            })).Err.Internal).kind,
+            { ValidationFailed: 'Only the authors of posts can crosspost them into tag 100' },
+            'Other agents cannot crosspost posts into author only tags',
+        );
+
+        t.deepEqual(
+            JSON.parse((await alice.call('posts', 'crosspost', {
+                post_address: policyPost.Ok,
+                tags: [101],
+            })).Err.Internal).kind,
+            { ValidationFailed: 'Posts cannot be crossposted into tag 101' },
+            'Nobody can crosspost into closed tags',
+        );
+    })();
+
     await s.consistent();
 
     /// SEARCH ///
@@ -486,6 +976,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
     const searchResult = await alice.call('posts', 'search', {
         query: { type: 'and', values: [{ type: 'exactly', values: 3 }, { type: 'exactly', values: 4 }] },
         exclude_crossposts: false,
+        apply_filters: false,
+        respect_moderation: false,
     });
     t.ok(
         searchResult.Ok &&
@@ -506,6 +998,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: 'and', values: [{ type: 'exactly', values: 3 }, { type: 'exactly', values: 4 }] },
             exclude_crossposts: true,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Crossposts can be excluded with search'
@@ -530,6 +1024,111 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         t.ok(ok, 'Post tags are valid with crosspost tags');
     })();
 
+    /// CROSSPOST ATTRIBUTION ///
+    // POSITIVE //
+    await (async () => {
+        const crossposts = await alice.call('posts', 'crossposts_of', {
+            post_address: testPost.Ok,
+        });
+        t.ok(
+            crossposts.Ok &&
+            crossposts.Ok.length == 2 &&
+            crossposts.Ok.every(crosspost => crosspost.crossposter == 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui') &&
+            crossposts.Ok.some(crosspost => crosspost.tag == 3) &&
+            crossposts.Ok.some(crosspost => crosspost.tag == 4),
+            'Crossposts record who made them',
+        );
+    })();
+
+    t.deepEquals(
+        await bob.call('posts', 'crosspost', { post_address: testPost.Ok, tags: [5] }),
+        { Ok: null },
+        'Other agents can crosspost posts',
+    );
+    await s.consistent();
+
+    // NEGATIVE //
+    t.deepEquals(
+        await bob.call('posts', 'remove_crosspost', { post_address: testPost.Ok, tag: 3 }),
+        { Err: { Internal: 'No crosspost of yours in that tag' } },
+        'Cannot remove crossposts of other agents',
+    );
+
+    // POSITIVE //
+    t.deepEquals(
+        await bob.call('posts', 'remove_crosspost', { post_address: testPost.Ok, tag: 5 }),
+        { Ok: null },
+        'Crossposts can be removed by the agent who made them',
+    );
+    await s.consistent();
+
+    t.deepEquals(
+        ((await alice.call('posts', 'crossposts_of', { post_address: testPost.Ok })).Ok || [])
+            .map(crosspost => crosspost.tag)
+            .sort(),
+        [3, 4],
+        'Removed crossposts are no longer listed',
+    );
+
+    /// RETAGGING ///
+    // POSITIVE //
+    t.deepEquals(
+        await alice.call('posts', 'retag_post', {
+            address: testPost.Ok,
+            add: [5],
+            remove: [2],
+        }),
+        { Ok: null },
+        'Posts can be retagged',
+    );
+
+    // NEGATIVE //
+    t.deepEquals(
+        await bob.call('posts', 'retag_post', {
+            address: testPost.Ok,
+            add: [6],
+            remove: [1],
+        }),
+        { Err: { Internal: 'Cannot retag post that is not yours.' } },
+        'Posts cannot be retagged by others',
+    );
+
+    t.deepEquals(
+        JSON.parse((await bob.call('posts', 'retag_post_raw', {
+            address: testPost.Ok,
+            add: [6],
+            remove: [],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Cannot link post to tag from author not in `key_hash`' },
+        'Other agents cannot link posts to tags',
+    );
+
+    await s.consistent();
+
+    await (async () => {
+        const post_tags = await alice.call('posts', 'post_tags', {
+            address: testPost.Ok,
+        });
+        const ok =
+            post_tags.Ok &&
+            post_tags.Ok.original_tags.length == 2 &&
+            post_tags.Ok.original_tags.includes(1) &&
+            post_tags.Ok.original_tags.includes(5) &&
+            post_tags.Ok.crosspost_tags.length == 2;
+        t.ok(ok, 'Post tags are valid after retagging');
+    })();
+
+    t.deepEquals(
+        await alice.call('posts', 'search', {
+            query: { type: "exactly", values: 2 },
+            exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
+        }),
+        { Ok: [] },
+        'Retagged posts are not found in removed tags'
+    );
+
     /// READING POST ///
     // POSITIVE //
     t.deepEqual(
@@ -540,7 +1139,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
             title: 'This is a test post',
             content: 'This is the content of the post',
             key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-            timestamp: '1970-01-01T00:00:00+00:00'
+            timestamp: isoTime(now),
+            edited: false,
         },
         'Posts are read correctly',
     );
@@ -554,16 +1154,47 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         'Cannot read invalid posts',
     );
 
-    /// UPDATE POSTS ///
+    /// READING TIMED POST ///
     // POSITIVE //
-    const updatedTestPostEntry = { ...testPostEntry, content: 'Updated test post' };
+    await (async () => {
+        const timedPost = await alice.call('posts', 'read_timed_post', {
+            address: testPost.Ok,
+        });
+        t.ok(
+            timedPost.Ok &&
+            timedPost.Ok.post.content == 'This is the content of the post' &&
+            timedPost.Ok.author == 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui' &&
+            timedPost.Ok.header_timestamp,
+            'Posts are read with their header timestamp and author',
+        );
+    })();
 
+    // NEGATIVE //
     t.deepEqual(
-        await alice.call('posts', 'update_post', {
-            old_address: testPost.Ok,
-            new_entry: updatedTestPostEntry,
+        await alice.call('posts', 'read_timed_post', {
+            address: 'invalid',
+        }),
+        { Err: { Internal: 'No entry at this address' } },
+        'Cannot read invalid timed posts',
+    );
+
+    /// UPDATE POSTS ///
+    // POSITIVE //
+    const updatedTestPostEntry = { ...testPostEntry, content: 'Updated test post' };
+
+    const updatedTestPost = entryAddress('post', {
+        title: 'This is a test post',
+        content: 'Updated test post',
+        key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+        timestamp: isoTime(now),
+    });
+
+    t.deepEqual(
+        await alice.call('posts', 'update_post', {
+            old_address: testPost.Ok,
+            new_entry: updatedTestPostEntry,
         }),
-        { Ok: 'QmZyKXyWQMt9J5MSDT31JXfkkeH4Z7m97NWjjS875TBco6' },
+        { Ok: updatedTestPost },
         'Posts can be updated',
     );
 
@@ -589,22 +1220,140 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
             title: 'This is a test post',
             content: 'Updated test post',
             key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-            timestamp: '1970-01-01T00:00:00+00:00'
+            timestamp: isoTime(now),
+            edited: true,
         },
-        'Updated posts are read correctly'
+        'Updated posts are read correctly, marked as edited'
     );
 
+    /// POST HISTORY ///
+    // POSITIVE //
+    await (async () => {
+        const timedPost = await alice.call('posts', 'read_timed_post', {
+            address: testPost.Ok,
+        });
+        t.ok(
+            timedPost.Ok &&
+            timedPost.Ok.edited &&
+            timedPost.Ok.address == updatedTestPost,
+            'Updated posts are marked as edited',
+        );
+
+        const history = await alice.call('posts', 'post_history', {
+            address: testPost.Ok,
+        });
+        t.ok(
+            history.Ok &&
+            history.Ok.length == 2 &&
+            history.Ok[0].address == testPost.Ok &&
+            !history.Ok[0].edited &&
+            history.Ok[0].post.content == 'This is the content of the post' &&
+            history.Ok[1].edited &&
+            history.Ok[1].post.content == 'Updated test post',
+            'Every revision of a post can be retrieved',
+        );
+    })();
+
+    /// TAG LINK AUTHORSHIP ///
+    // NEGATIVE //
+    await (async () => {
+        const alicePost = await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: 'Only alice can tag this' },
+            tags: [7],
+        });
+        await s.consistent();
+
+        t.deepEqual(
+            await bob.call('posts', 'delete_post', {
+                address: alicePost.Ok,
+            }),
+            { Err: { Internal: 'Cannot delete post that is not yours.' } },
+            'Other agents cannot delete a post',
+        );
+
+        t.deepEqual(
+            JSON.parse((await bob.call('posts', 'retag_post_raw', {
+                address: alicePost.Ok,
+                add: [],
+                remove: [7],
+            })).Err.Internal).kind,
+            { ValidationFailed: 'Cannot link post to tag from author not in `key_hash`' },
+            'Other agents cannot remove the tags of a post',
+        );
+        await s.consistent();
+
+        t.deepEqual(
+            (await alice.call('posts', 'post_tags', { address: alicePost.Ok })).Ok.original_tags,
+            [7],
+            'Tags of a post are kept when other agents try to remove them',
+        );
+
+        t.ok(
+            (await alice.call('posts', 'user_posts', {
+                author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                apply_filters: false,
+            })).Ok.includes(alicePost.Ok),
+            'Author links of a post are kept when other agents try to delete it',
+        );
+    })();
+
     /// DELETING POSTS ///
     // POSITIVE //
+    await (async () => {
+        const crosspostedPost = await alice.call('posts', 'create_post', {
+            post: { ...testPostEntry, title: 'Crossposted by bob' },
+            tags: [7],
+        });
+        await s.consistent();
+        t.deepEqual(
+            await bob.call('posts', 'crosspost', {
+                post_address: crosspostedPost.Ok,
+                tags: [8],
+            }),
+            { Ok: null },
+            'Other agents can crosspost a post',
+        );
+        await s.consistent();
+
+        const deletion = await alice.call('posts', 'delete_post', {
+            address: crosspostedPost.Ok,
+        });
+        t.ok(
+            deletion.Ok && deletion.Ok.deletion_address && deletion.Ok.failed_links.length == 0,
+            'Authors can delete posts that other agents crossposted',
+        );
+    })();
+
+    await (async () => {
+        t.deepEqual(
+            await alice.call('posts', 'delete_post', {
+                address: testPost.Ok
+            }),
+            { Ok: { deletion_address: deletionAddress(updatedTestPost), failed_links: [] } },
+            'Posts can be deleted',
+        );
+    })();
+
+    await s.consistent();
+
+    /// REPAIRING POSTS ///
+    // POSITIVE //
     t.deepEqual(
-        await alice.call('posts', 'delete_post', {
+        await alice.call('posts', 'repair_post', {
             address: testPost.Ok
         }),
-        { Ok: 'QmPRd5wyHCNvuQsHAR9PgYgRj4UhGQraToDf1dQUgBLyNn' },
-        'Posts can be deleted',
+        { Ok: { added_links: [], removed_links: [], failed_links: [] } },
+        'Deleted posts leave no dangling links',
     );
 
-    await s.consistent();
+    // NEGATIVE //
+    t.deepEqual(
+        await alice.call('posts', 'repair_post', {
+            address: 'invalid'
+        }),
+        { Err: { Internal: 'No entry at this address' } },
+        'Invalid posts cannot be repaired',
+    );
 
     /// READING POSTS ///
     // NEGATIVE //
@@ -622,6 +1371,8 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
         await alice.call('posts', 'search', {
             query: { type: "exactly", values: 1 },
             exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
         }),
         { Ok: [] },
         'Query finds no post after deletion'
@@ -636,23 +1387,24 @@ diorama.registerScenario('Test posts zome', async (s, t, { alice }) => {
 });
 
 diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
+    const now = currentTime();
     const postAddress = await alice.call('posts', 'create_post', {
         post: {
             title: 'Testing post',
             content: 'This post is used for testing the comments zome',
-            utc_unix_time: 0,
+            utc_unix_time: now,
         },
         tags: [0]
     });
 
     const commentEntry = {
         content: 'This is a comment!',
-        utc_unix_time: 0,
+        utc_unix_time: now,
     };
 
     const otherCommentEntry = {
         content: 'This is another comment!',
-        utc_unix_time: 0,
+        utc_unix_time: now,
     };
 
     const commentAddress = await alice.call('comments', 'create_comment', {
@@ -667,16 +1419,24 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
         target: commentAddress.Ok,
     });
 
-    t.deepEquals(
+    t.deepEqual(
         commentAddress,
-        { Ok: 'QmeAjLTzh2tV8T7jeAGgCo41S1zpHwbzBfbH52mTzAZ1Ws' },
-        'Address is correct, comments can be made on posts'
+        { Ok: entryAddress('comment', {
+            content: 'This is a comment!',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+        }) },
+        'Comments can be made on posts',
     );
 
-    t.deepEquals(
+    t.deepEqual(
         otherCommentAddress,
-        { Ok: 'QmP8zSSeTCj7NpfbMqUuSkFDxSZjVhkWhrwcqHvwKqUBdv' },
-        'Address is correct, comments can be made on other comments'
+        { Ok: entryAddress('comment', {
+            content: 'This is another comment!',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+        }) },
+        'Comments can be made on other comments',
     );
 
     t.deepEqual(
@@ -686,11 +1446,52 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
         {
             content: 'This is a comment!',
             key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-            timestamp: '1970-01-01T00:00:00+00:00'
+            timestamp: isoTime(now),
+            edited: false,
         },
         'Comments are read correctly'
     );
 
+    await (async () => {
+        const timedComment = await alice.call('comments', 'read_timed_comment', {
+            address: commentAddress.Ok,
+        });
+        t.ok(
+            timedComment.Ok &&
+            timedComment.Ok.comment.content == 'This is a comment!' &&
+            timedComment.Ok.author == 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui' &&
+            timedComment.Ok.header_timestamp,
+            'Comments are read with their header timestamp and author',
+        );
+    })();
+
+    t.deepEquals(
+        JSON.parse((await alice.call('comments', 'create_comment', {
+            comment: { ...commentEntry, utc_unix_time: 0 },
+            target: postAddress.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Timestamp is too far from the time of the chain header' },
+        'Cannot create comment with timestamp far from header time',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('comments', 'create_comment', {
+            comment: { ...commentEntry, content: 'x'.repeat(10001) },
+            target: postAddress.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Comment must be at most 10000 characters' },
+        'Cannot create comment longer than the DNA allows',
+    );
+
+    t.deepEquals(
+        JSON.parse((await alice.call('comments', 'create_comment', {
+            comment: { ...commentEntry, content: 'Bell\u0007' },
+            target: postAddress.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Comment cannot contain control characters' },
+        'Cannot create comment with control characters',
+    );
+
     const updatedCommentEntry = { ...commentEntry, content: 'This is an updated comment.' };
 
     const updatedCommentAddress = await alice.call('comments', 'update_comment', {
@@ -700,7 +1501,11 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
 
     t.deepEqual(
         updatedCommentAddress,
-        { Ok: 'Qma4WG8UZjqnroMnUxbmxEyUgaKgvVAZqkwGWaeYNAHzpc' },
+        { Ok: entryAddress('comment', {
+            content: 'This is an updated comment.',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+        }) },
         'Comments can be updated',
     );
 
@@ -713,14 +1518,31 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
         {
             content: 'This is an updated comment.',
             key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
-            timestamp: '1970-01-01T00:00:00+00:00'
+            timestamp: isoTime(now),
+            edited: true,
         }
-        , 'Updated comments are read correctly'
+        , 'Updated comments are read correctly, marked as edited'
     );
 
+    await (async () => {
+        const history = await alice.call('comments', 'comment_history', {
+            address: commentAddress.Ok,
+        });
+        t.ok(
+            history.Ok &&
+            history.Ok.length == 2 &&
+            !history.Ok[0].edited &&
+            history.Ok[0].comment.content == 'This is a comment!' &&
+            history.Ok[1].edited &&
+            history.Ok[1].comment.content == 'This is an updated comment.',
+            'Every revision of a comment can be retrieved',
+        );
+    })();
+
     t.deepEqual(
         await alice.call('comments', 'comments_from_address', {
             address: postAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [commentAddress.Ok] },
         'Comments can be retrieved from the address of a post',
@@ -729,6 +1551,7 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
     t.deepEqual(
         await alice.call('comments', 'comments_from_address', {
             address: commentAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [otherCommentAddress.Ok] },
         'Comments can be retrieved from the address of a comment',
@@ -740,7 +1563,7 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
         await alice.call('comments', 'delete_comment', {
             address: otherCommentAddress.Ok
         }),
-        { Ok: 'QmWZHszfNJSdC4knQtRVqLPCtrTx2iRJ8d83N6srB8TeLX' },
+        { Ok: deletionAddress(otherCommentAddress.Ok) },
         'Comments can be deleted',
     );
 
@@ -757,6 +1580,7 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
     t.deepEqual(
         await alice.call('comments', 'comments_from_address', {
             address: commentAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [] },
         'Deleted Comments can\'t be retrieved from the address of a comment',
@@ -766,8 +1590,8 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
         await alice.call('comments', 'delete_comment', {
             address: commentAddress.Ok
         }),
-        { Ok: 'QmZBTscKSa7AteGEcKaCSgkC1ZVYHKDfgJLxR3BxRobQNe' },
-        'Comments can be deleted',
+        { Ok: deletionAddress(updatedCommentAddress.Ok) },
+        'Updated comments can be deleted',
     );
 
     await s.consistent();
@@ -783,10 +1607,1486 @@ diorama.registerScenario('Test comments zome', async (s, t, { alice }) => {
     t.deepEqual(
         await alice.call('comments', 'comments_from_address', {
             address: postAddress.Ok,
+            apply_filters: false,
         }),
         { Ok: [] },
         'Deleted comments can\'t be retrieved from the address of a post',
     );
+
+    /// TOMBSTONES ///
+    const parentAddress = await alice.call('comments', 'create_comment', {
+        comment: commentEntry,
+        target: postAddress.Ok,
+    });
+
+    await s.consistent();
+
+    const replyAddress = await alice.call('comments', 'create_comment', {
+        comment: otherCommentEntry,
+        target: parentAddress.Ok,
+    });
+
+    await s.consistent();
+
+    // POSITIVE //
+    t.ok(
+        (await alice.call('comments', 'tombstone_comment', {
+            address: parentAddress.Ok,
+            utc_unix_time: now,
+        })).Ok,
+        'Comments can be replaced with tombstones',
+    );
+
+    t.ok(
+        (await alice.call('posts', 'tombstone_post', {
+            address: postAddress.Ok,
+            utc_unix_time: now,
+        })).Ok,
+        'Posts can be replaced with tombstones',
+    );
+
+    await s.consistent();
+
+    t.deepEqual(
+        (await alice.call('comments', 'read_comment', {
+            address: parentAddress.Ok,
+        })).Ok,
+        {
+            content: '[deleted]',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+            deleted: true,
+            edited: true,
+        },
+        'Tombstoned comments are read as deleted',
+    );
+
+    t.deepEqual(
+        (await alice.call('posts', 'read_post', {
+            address: postAddress.Ok,
+        })).Ok,
+        {
+            title: '[deleted]',
+            content: '[deleted]',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+            deleted: true,
+            edited: true,
+        },
+        'Tombstoned posts are read as deleted',
+    );
+
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', {
+            address: postAddress.Ok,
+            apply_filters: false,
+        }),
+        { Ok: [parentAddress.Ok] },
+        'Tombstoned comments can still be retrieved from tombstoned posts',
+    );
+
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', {
+            address: parentAddress.Ok,
+            apply_filters: false,
+        }),
+        { Ok: [replyAddress.Ok] },
+        'Replies can still be retrieved from tombstoned comments',
+    );
+
+    await (async () => {
+        const history = await alice.call('comments', 'comment_history', {
+            address: parentAddress.Ok,
+        });
+        t.ok(
+            history.Ok &&
+            history.Ok.length == 2 &&
+            history.Ok.every(revision => revision.comment.content == '[deleted]'),
+            'Revisions of tombstoned comments are redacted',
+        );
+    })();
+
+    await (async () => {
+        const history = await alice.call('posts', 'post_history', {
+            address: postAddress.Ok,
+        });
+        t.ok(
+            history.Ok &&
+            history.Ok.length == 2 &&
+            history.Ok.every(revision =>
+                revision.post.title == '[deleted]' && revision.post.content == '[deleted]'),
+            'Revisions of tombstoned posts are redacted',
+        );
+    })();
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('comments', 'update_comment', {
+            old_address: parentAddress.Ok,
+            new_entry: commentEntry,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Cannot alter comment that has been deleted.' },
+        'Tombstoned comments cannot be edited',
+    );
+
+    t.deepEqual(
+        JSON.parse((await alice.call('comments', 'create_comment_raw', {
+            comment: {
+                content: '[deleted]',
+                key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+                timestamp: isoTime(now),
+                deleted: true,
+            },
+            target: replyAddress.Ok,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Comments cannot be created deleted.' },
+        'Cannot create comment that is already deleted',
+    );
+});
+
+diorama.registerScenario('Test post identity across updates', async (s, t, { alice }) => {
+    const now = currentTime();
+    const postEntry = {
+        title: 'Testing post',
+        content: 'This post is used for testing updates',
+        utc_unix_time: now,
+    };
+
+    const postAddress = await alice.call('posts', 'create_post', {
+        post: postEntry,
+        tags: [0],
+    });
+
+    await s.consistent();
+
+    const updatedPostAddress = await alice.call('posts', 'update_post', {
+        old_address: postAddress.Ok,
+        new_entry: { ...postEntry, content: 'This post has been updated' },
+    });
+
+    await s.consistent();
+
+    /// COMMENTING ON UPDATED POSTS ///
+    const commentAddress = await alice.call('comments', 'create_comment', {
+        comment: { content: 'Commenting on the updated post', utc_unix_time: now },
+        target: updatedPostAddress.Ok,
+    });
+
+    t.ok(
+        (await alice.call('votes', 'vote', {
+            fraction: 1,
+            in_terms_of: [0],
+            utc_unix_time: now,
+            target: updatedPostAddress.Ok,
+        })).Ok,
+        'Can vote on updated posts',
+    );
+
+    await s.consistent();
+
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', {
+            address: postAddress.Ok,
+            apply_filters: false,
+        }),
+        { Ok: [commentAddress.Ok] },
+        'Comments on updated posts are found from the original address',
+    );
+
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', {
+            address: updatedPostAddress.Ok,
+            apply_filters: false,
+        }),
+        { Ok: [commentAddress.Ok] },
+        'Comments on updated posts are found from the updated address',
+    );
+
+    t.deepEqual(
+        (await alice.call('votes', 'votes_from_address', {
+            address: postAddress.Ok,
+            apply_filters: false,
+        })).Ok.map((vote) => vote.target_hash),
+        [postAddress.Ok],
+        'Votes on updated posts are cast on the original address',
+    );
+
+    t.deepEqual(
+        await alice.call('posts', 'post_tags', {
+            address: updatedPostAddress.Ok,
+        }),
+        { Ok: { original_tags: [0], crosspost_tags: [] } },
+        'Tags of updated posts are found from the updated address',
+    );
+
+    /// READING ///
+    t.deepEqual(
+        await alice.call('posts', 'user_posts', {
+            author: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            apply_filters: false,
+        }),
+        { Ok: [postAddress.Ok] },
+        'Updated posts are listed by their original address',
+    );
+
+    t.equal(
+        (await alice.call('posts', 'read_post', {
+            address: postAddress.Ok,
+        })).Ok.content,
+        'This post has been updated',
+        'Reading the original address gives the latest version',
+    );
+
+    /// UPDATING TWICE ///
+    t.ok(
+        (await alice.call('posts', 'update_post', {
+            old_address: postAddress.Ok,
+            new_entry: { ...postEntry, content: 'This post has been updated twice' },
+        })).Ok,
+        'Updating the original address updates the latest version',
+    );
+
+    await s.consistent();
+
+    t.deepEqual(
+        (await alice.call('posts', 'post_history', {
+            address: updatedPostAddress.Ok,
+        })).Ok.map((revision) => revision.post.content),
+        [
+            'This post is used for testing updates',
+            'This post has been updated',
+            'This post has been updated twice',
+        ],
+        'History of an updated post is found from any of its addresses',
+    );
+});
+
+diorama.registerScenario('Test post kinds', async (s, t, { alice }) => {
+    const now = currentTime();
+    const linkPostEntry = {
+        title: 'A link post',
+        content: 'Have a look at this',
+        utc_unix_time: now,
+        kind: {
+            type: 'link',
+            url: 'https://example.com/article',
+            preview: { title: 'An article', description: null, image_url: null },
+        },
+    };
+
+    /// CREATING POSTS ///
+    // POSITIVE //
+    const linkPost = await alice.call('posts', 'create_post', {
+        post: linkPostEntry,
+        tags: [0],
+    });
+    t.ok(linkPost.Ok, 'Link posts can be created');
+
+    t.ok(
+        (await alice.call('posts', 'create_post', {
+            post: {
+                title: 'An image post',
+                content: '',
+                utc_unix_time: now,
+                kind: { type: 'image', url: 'http://example.com/cat.png', alt_text: 'A cat' },
+            },
+            tags: [0],
+        })).Ok,
+        'Image posts can be created',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...linkPostEntry, kind: { type: 'link', url: 'not a url', preview: null } },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'URL must start with http:// or https://' },
+        'Link posts need a valid URL',
+    );
+
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: { ...linkPostEntry, kind: { type: 'file', url: 'https://example.com/a.zip', name: '' } },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'File name cannot be empty' },
+        'File posts need a file name',
+    );
+
+    /// DNA PROPERTIES ///
+    t.deepEqual(
+        (await alice.call('posts', 'get_properties', {})).Ok,
+        {
+            max_title_length: 300,
+            max_post_length: 40000,
+            max_comment_length: 10000,
+            allowed_post_kinds: ['text', 'link', 'image', 'file', 'poll'],
+            max_tags_per_post: 8,
+            max_crosspost_count: 16,
+            max_crossposts_per_hour: 30,
+            crosspost_policies: { 100: 'author_only', 101: 'closed' },
+            max_delegation_depth: 5,
+            max_comment_depth: 256,
+        },
+        'Network configuration is read from the DNA properties, with defaults for unset properties',
+    );
+
+    t.deepEqual(
+        (await alice.call('posts', 'create_post', {
+            post: linkPostEntry,
+            tags: [0, 1, 2, 3, 4, 5, 6, 7, 8],
+        })).Err,
+        { Internal: 'Posts can have at most 8 tags' },
+        'Posts cannot have more tags than the DNA allows',
+    );
+
+    const fullyTaggedPost = await alice.call('posts', 'create_post', {
+        post: { ...linkPostEntry, title: 'A post with every tag it can have' },
+        tags: [0, 1, 2, 3, 4, 5, 6, 7],
+    });
+    await s.consistent();
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'retag_post_raw', {
+            address: fullyTaggedPost.Ok,
+            add: [8],
+            remove: [],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Posts can have at most 8 tags' },
+        'Tag links over the limit are rejected in validation',
+    );
+
+    t.deepEqual(
+        await alice.call('posts', 'crosspost', {
+            post_address: linkPost.Ok,
+            tags: [...Array(17).keys()],
+        }),
+        { Err: { Internal: 'You can crosspost a post to at most 16 tags' } },
+        'Agents cannot crosspost a post to more tags than the DNA allows',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        await alice.call('posts', 'crossposts_of', { post_address: linkPost.Ok }),
+        { Ok: [] },
+        'Crossposts over the limit are not partially made',
+    );
+
+    t.deepEqual(
+        await alice.call('posts', 'crosspost', {
+            post_address: linkPost.Ok,
+            tags: [...Array(16).keys()],
+        }),
+        { Ok: null },
+        'Agents can crosspost a post to as many tags as the DNA allows',
+    );
+
+    const otherLinkPost = await alice.call('posts', 'create_post', {
+        post: { ...linkPostEntry, title: 'Another link post' },
+        tags: [0],
+    });
+    await s.consistent();
+
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'crosspost', {
+            post_address: otherLinkPost.Ok,
+            tags: [...Array(16).keys()],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'You can crosspost at most 30 times an hour' },
+        'Agents cannot crosspost more often than the DNA allows',
+    );
+
+    await s.consistent();
+
+    /// READING POSTS ///
+    t.deepEqual(
+        (await alice.call('posts', 'read_post', {
+            address: linkPost.Ok,
+        })).Ok,
+        {
+            title: 'A link post',
+            content: 'Have a look at this',
+            key_hash: 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui',
+            timestamp: isoTime(now),
+            kind: {
+                type: 'link',
+                url: 'https://example.com/article',
+                preview: { title: 'An article', description: null, image_url: null },
+            },
+            edited: false,
+        },
+        'Link posts are read with their kind',
+    );
+});
+
+diorama.registerScenario('Test files zome', async (s, t, { alice }) => {
+    const now = currentTime();
+    const fileData = Buffer.from('hello world').toString('base64');
+
+    /// UPLOADING ///
+    // POSITIVE //
+    const manifestAddress = await alice.call('files', 'upload_file', {
+        name: 'hello.txt',
+        mime_type: 'text/plain',
+        data: fileData,
+    });
+    t.ok(manifestAddress.Ok, 'Files can be uploaded');
+
+    // NEGATIVE //
+    t.deepEqual(
+        await alice.call('files', 'upload_file', {
+            name: 'hello.txt',
+            mime_type: 'text/plain',
+            data: 'not base64!',
+        }),
+        { Err: { Internal: 'File data is not valid base64' } },
+        'Files must be base64 encoded',
+    );
+
+    await s.consistent();
+
+    /// READING ///
+    const manifest = await alice.call('files', 'get_file_manifest', {
+        address: manifestAddress.Ok,
+    });
+    t.ok(
+        manifest.Ok &&
+        manifest.Ok.name == 'hello.txt' &&
+        manifest.Ok.mime_type == 'text/plain' &&
+        manifest.Ok.size == 11 &&
+        manifest.Ok.hash == 'b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9' &&
+        manifest.Ok.chunks.length == 1,
+        'File manifests can be read',
+    );
+
+    t.deepEqual(
+        await alice.call('files', 'get_chunk', {
+            address: manifest.Ok.chunks[0],
+        }),
+        { Ok: { data: fileData } },
+        'File chunks can be read',
+    );
+
+    /// ATTACHING ///
+    // POSITIVE //
+    const postAddress = await alice.call('posts', 'create_post', {
+        post: {
+            title: 'A post with a file',
+            content: '',
+            utc_unix_time: now,
+            attachments: [manifestAddress.Ok],
+        },
+        tags: [0],
+    });
+    t.ok(postAddress.Ok, 'Files can be attached to posts');
+
+    t.ok(
+        (await alice.call('comments', 'create_comment', {
+            comment: {
+                content: 'A comment with a file',
+                utc_unix_time: now,
+                attachments: [manifestAddress.Ok],
+            },
+            target: postAddress.Ok,
+        })).Ok,
+        'Files can be attached to comments',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: {
+                title: 'A post with something that is not a file',
+                content: '',
+                utc_unix_time: now,
+                attachments: [postAddress.Ok],
+            },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Attachments must be file manifests' },
+        'Only files can be attached to posts',
+    );
+
+    /// IMAGE POSTS ///
+    const imageAddress = await alice.call('files', 'upload_file', {
+        name: 'pixel.png',
+        mime_type: 'image/png',
+        data: Buffer.from('not really a png').toString('base64'),
+    });
+
+    await s.consistent();
+
+    // POSITIVE //
+    t.ok(
+        (await alice.call('posts', 'create_post', {
+            post: {
+                title: 'An uploaded image',
+                content: '',
+                utc_unix_time: now,
+                kind: { type: 'image', manifest: imageAddress.Ok, alt_text: null },
+            },
+            tags: [0],
+        })).Ok,
+        'Image posts can use uploaded images',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: {
+                title: 'An uploaded text file',
+                content: '',
+                utc_unix_time: now,
+                kind: { type: 'image', manifest: manifestAddress.Ok, alt_text: null },
+            },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Image must be an image' },
+        'Image posts cannot use files that are not images',
+    );
+
+    t.deepEqual(
+        JSON.parse((await alice.call('posts', 'create_post', {
+            post: {
+                title: 'Two images at once',
+                content: '',
+                utc_unix_time: now,
+                kind: {
+                    type: 'image',
+                    url: 'http://example.com/cat.png',
+                    manifest: imageAddress.Ok,
+                    alt_text: null,
+                },
+            },
+            tags: [0],
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Image posts need either a URL or a file manifest' },
+        'Image posts cannot have both a URL and a file manifest',
+    );
+});
+
+diorama.registerScenario('Test polls zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const pollPost = await alice.call('posts', 'create_post', {
+        post: { title: 'Tabs or spaces?', content: '', utc_unix_time: now, kind: { type: 'poll' } },
+        tags: [0],
+    });
+    const textPost = await alice.call('posts', 'create_post', {
+        post: { title: 'Not a poll', content: '', utc_unix_time: now },
+        tags: [0],
+    });
+    await s.consistent();
+
+    /// CREATING POLLS ///
+    // POSITIVE //
+    const poll = await alice.call('polls', 'create_poll', {
+        post: pollPost.Ok,
+        options: ['Tabs', 'Spaces'],
+        multiple_choice: false,
+        opens_at: null,
+        closes_at: null,
+        utc_unix_time: now,
+    });
+    t.ok(poll.Ok, 'Polls can be attached to poll posts');
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('polls', 'create_poll', {
+            post: textPost.Ok,
+            options: ['Yes', 'No'],
+            multiple_choice: false,
+            opens_at: null,
+            closes_at: null,
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Polls can only be attached to poll posts' },
+        'Polls cannot be attached to other kinds of posts',
+    );
+
+    const otherPollPost = await alice.call('posts', 'create_post', {
+        post: { title: 'Closed poll', content: '', utc_unix_time: now, kind: { type: 'poll' } },
+        tags: [0],
+    });
+    await s.consistent();
+
+    t.deepEqual(
+        JSON.parse((await alice.call('polls', 'create_poll', {
+            post: otherPollPost.Ok,
+            options: ['Only option'],
+            multiple_choice: false,
+            opens_at: null,
+            closes_at: null,
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Polls must have between 2 and 32 options' },
+        'Polls need at least two options',
+    );
+
+    t.ok(
+        (await bob.call('polls', 'create_poll', {
+            post: otherPollPost.Ok,
+            options: ['Yes', 'No'],
+            multiple_choice: false,
+            opens_at: null,
+            closes_at: null,
+            utc_unix_time: now,
+        })).Err,
+        'Polls cannot be attached to posts of other agents',
+    );
+
+    t.ok(
+        (await alice.call('polls', 'create_poll', {
+            post: otherPollPost.Ok,
+            options: ['Yes', 'No'],
+            multiple_choice: true,
+            opens_at: now - 100,
+            closes_at: now - 50,
+            utc_unix_time: now,
+        })).Ok,
+        'Polls can have a closing time',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        await alice.call('polls', 'create_poll', {
+            post: pollPost.Ok,
+            options: ['Both', 'Neither'],
+            multiple_choice: false,
+            opens_at: null,
+            closes_at: null,
+            utc_unix_time: now,
+        }),
+        { Err: { Internal: 'Post already has a poll' } },
+        'Posts can only have one poll',
+    );
+    t.deepEqual(
+        (await bob.call('polls', 'get_poll', { post: pollPost.Ok })).Ok.options,
+        ['Tabs', 'Spaces'],
+        'The poll of a post is the first one attached to it',
+    );
+
+    /// VOTING ///
+    // POSITIVE //
+    t.ok(
+        (await alice.call('polls', 'poll_vote', { post: pollPost.Ok, choices: [0], utc_unix_time: now })).Ok,
+        'Agents can vote in polls',
+    );
+    t.ok(
+        (await bob.call('polls', 'poll_vote', { post: pollPost.Ok, choices: [1], utc_unix_time: now })).Ok,
+        'Other agents can vote in polls',
+    );
+    await s.consistent();
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('polls', 'poll_vote', {
+            post: pollPost.Ok,
+            choices: [1],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: "You've already voted in this poll" },
+        'Agents can only vote in a poll once',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('polls', 'poll_vote', {
+            post: pollPost.Ok,
+            choices: [0, 1],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Only one option can be chosen in this poll' },
+        'Single choice polls only allow one choice',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('polls', 'poll_vote', {
+            post: pollPost.Ok,
+            choices: [2],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Poll vote chooses an option that does not exist' },
+        'Poll votes must choose existing options',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('polls', 'poll_vote', {
+            post: otherPollPost.Ok,
+            choices: [0],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Poll is closed' },
+        'Closed polls cannot be voted in',
+    );
+
+    /// RESULTS ///
+    t.deepEqual(
+        (await alice.call('polls', 'poll_results', { post: pollPost.Ok, weighted: false })).Ok,
+        {
+            poll: poll.Ok,
+            options: [
+                { option: 'Tabs', votes: 1, weight: 1000000 },
+                { option: 'Spaces', votes: 1, weight: 1000000 },
+            ],
+            voters: 2,
+        },
+        'Poll results count every voter once',
+    );
+
+    const bobPost = await bob.call('posts', 'create_post', {
+        post: { title: 'Spaces are better', content: '', utc_unix_time: now },
+        tags: [0],
+    });
+    await s.consistent();
+    await alice.call('votes', 'vote', {
+        utc_unix_time: now,
+        fraction: 1,
+        in_terms_of: [0],
+        target: bobPost.Ok,
+    });
+    await s.consistent();
+
+    t.deepEqual(
+        (await alice.call('polls', 'poll_results', { post: pollPost.Ok, weighted: true })).Ok.options,
+        [
+            { option: 'Tabs', votes: 1, weight: 1000000 },
+            { option: 'Spaces', votes: 1, weight: 2000000 },
+        ],
+        'Weighted poll results count agents by how the caller has voted on them',
+    );
+});
+
+diorama.registerScenario('Test saved zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const post = await alice.call('posts', 'create_post', {
+        post: { title: 'Worth saving', content: 'Read this later', utc_unix_time: now },
+        tags: [0],
+    });
+    await s.consistent();
+    const comment = await alice.call('comments', 'create_comment', {
+        comment: { content: 'Also worth saving', utc_unix_time: now },
+        target: post.Ok,
+    });
+    await s.consistent();
+
+    /// SAVING ///
+    // POSITIVE //
+    t.ok(
+        (await alice.call('saved', 'save', { address: post.Ok, note: 'For the weekend', utc_unix_time: now })).Ok,
+        'Posts can be saved',
+    );
+    t.ok(
+        (await alice.call('saved', 'save', { address: comment.Ok, note: null, utc_unix_time: now })).Ok,
+        'Comments can be saved',
+    );
+
+    await (async () => {
+        const saved = await alice.call('saved', 'saved_items', { page: 0 });
+        t.ok(
+            saved.Ok &&
+            saved.Ok.length == 2 &&
+            saved.Ok.some(item => item.target == post.Ok && item.target_type == 'post' && item.note == 'For the weekend') &&
+            saved.Ok.some(item => item.target == comment.Ok && item.target_type == 'comment' && item.note === null),
+            'Saved posts and comments are listed',
+        );
+    })();
+
+    t.deepEqual(
+        await bob.call('saved', 'saved_items', { page: 0 }),
+        { Ok: [] },
+        'Saved items are private to the agent that saved them',
+    );
+
+    t.deepEqual(
+        await alice.call('saved', 'saved_items', { page: 1 }),
+        { Ok: [] },
+        'Saved items are paged',
+    );
+
+    // NEGATIVE //
+    await (async () => {
+        const anchor = await alice.call('anchors', 'anchor', {
+            anchor: { anchor_type: 'tag', anchor_text: '0' },
+        });
+        t.ok(
+            (await alice.call('saved', 'save', { address: anchor.Ok, note: null, utc_unix_time: now })).Err,
+            'Only posts and comments can be saved',
+        );
+    })();
+
+    /// UNSAVING ///
+    // POSITIVE //
+    t.ok(
+        (await alice.call('saved', 'unsave', { address: post.Ok, utc_unix_time: now })).Ok,
+        'Saved items can be unsaved',
+    );
+
+    t.deepEqual(
+        ((await alice.call('saved', 'saved_items', { page: 0 })).Ok || []).map(item => item.target),
+        [comment.Ok],
+        'Unsaved items are no longer listed',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        await alice.call('saved', 'unsave', { address: post.Ok, utc_unix_time: now }),
+        { Err: { Internal: 'Item is not saved' } },
+        'Items that are not saved cannot be unsaved',
+    );
+});
+
+diorama.registerScenario('Test blocklist zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const bobAddress = await bob.call('posts', 'get_agent_address', {});
+    const alicePost = await alice.call('posts', 'create_post', {
+        post: { title: 'Gardening tips', content: 'Water in the morning', utc_unix_time: now },
+        tags: [0],
+    });
+    const bobPost = await bob.call('posts', 'create_post', {
+        post: { title: 'More gardening', content: 'Mulch everything', utc_unix_time: now },
+        tags: [0],
+    });
+    const spamPost = await alice.call('posts', 'create_post', {
+        post: { title: 'Buy CHEAP seeds', content: '', utc_unix_time: now },
+        tags: [1],
+    });
+    await s.consistent();
+    const bobComment = await bob.call('comments', 'create_comment', {
+        comment: { content: 'Thanks!', utc_unix_time: now },
+        target: alicePost.Ok,
+    });
+    await bob.call('votes', 'vote', {
+        utc_unix_time: now,
+        fraction: 1,
+        in_terms_of: [0],
+        target: alicePost.Ok,
+    });
+    await s.consistent();
+
+    /// BLOCKING ///
+    // POSITIVE //
+    t.deepEqual(
+        await alice.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [], tags: [], keywords: [] } },
+        'Agents start with nothing blocked',
+    );
+
+    t.ok(
+        (await alice.call('blocklist', 'block', {
+            blocks: { agents: [bobAddress], tags: [], keywords: [' Cheap '] },
+            utc_unix_time: now,
+        })).Ok,
+        'Agents and keywords can be blocked',
+    );
+    t.ok(
+        (await alice.call('blocklist', 'block', {
+            blocks: { agents: [], tags: [1], keywords: [] },
+            utc_unix_time: now + 1,
+        })).Ok,
+        'Tags can be blocked',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [bobAddress], tags: [1], keywords: ['cheap'] } },
+        'Blocks accumulate and keywords are stored lowercase',
+    );
+
+    t.deepEqual(
+        await bob.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [], tags: [], keywords: [] } },
+        'Blocks are private to the agent that made them',
+    );
+
+    /// FILTERED READS ///
+    t.deepEqual(
+        ((await alice.call('posts', 'search', {
+            query: { type: 'or', values: [{ type: 'exactly', values: 0 }, { type: 'exactly', values: 1 }] },
+            exclude_crossposts: false,
+            apply_filters: true,
+            respect_moderation: false,
+        })).Ok || []).map(result => result.address),
+        [alicePost.Ok],
+        'Search leaves out posts by blocked agents, with blocked keywords or in blocked tags',
+    );
+
+    t.equal(
+        ((await alice.call('posts', 'search', {
+            query: { type: 'or', values: [{ type: 'exactly', values: 0 }, { type: 'exactly', values: 1 }] },
+            exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation: false,
+        })).Ok || []).length,
+        3,
+        'Search can ignore blocks',
+    );
+
+    t.deepEqual(
+        await alice.call('posts', 'user_posts', { author: bobAddress, apply_filters: true }),
+        { Ok: [] },
+        'Posts by blocked agents are left out of their posts',
+    );
+
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', { address: alicePost.Ok, apply_filters: true }),
+        { Ok: [] },
+        'Comments by blocked agents are left out',
+    );
+    t.deepEqual(
+        await alice.call('comments', 'comments_from_address', { address: alicePost.Ok, apply_filters: false }),
+        { Ok: [bobComment.Ok] },
+        'Comments can ignore blocks',
+    );
+
+    t.deepEqual(
+        await alice.call('votes', 'votes_from_address', { address: alicePost.Ok, apply_filters: true }),
+        { Ok: [] },
+        'Votes by blocked agents are left out',
+    );
+
+    t.deepEqual(
+        await bob.call('posts', 'user_posts', { author: bobAddress, apply_filters: true }),
+        { Ok: [bobPost.Ok] },
+        'Blocks only apply to the agent that made them',
+    );
+
+    const aliceAddress = 'HcScjwO9ji9633ZYxa6IYubHJHW6ctfoufv5eq4F7ZOxay8wR76FP4xeG9pY3ui';
+    t.deepEqual(
+        await alice.call('blocklist', 'is_blocked', {
+            items: [
+                { author: aliceAddress, tags: [0], texts: ['The cheapest seeds'] },
+                { author: aliceAddress, tags: [0], texts: ['So cheap!'] },
+                { author: aliceAddress, tags: [1], texts: [] },
+                { author: bobAddress, tags: [], texts: [] },
+            ],
+        }),
+        { Ok: [false, true, true, true] },
+        'Blocked keywords only match whole words',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('blocklist', 'block', {
+            blocks: { agents: [], tags: [], keywords: ['  '] },
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Blocked keywords cannot be empty' },
+        'Blocked keywords cannot be empty',
+    );
+
+    /// UNBLOCKING ///
+    t.ok(
+        (await alice.call('blocklist', 'unblock', {
+            blocks: { agents: [bobAddress], tags: [], keywords: ['CHEAP'] },
+            utc_unix_time: now + 2,
+        })).Ok,
+        'Blocks can be removed',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [], tags: [1], keywords: [] } },
+        'Only unblocked agents, tags and keywords are removed',
+    );
+
+    t.deepEqual(
+        await alice.call('posts', 'user_posts', { author: bobAddress, apply_filters: true }),
+        { Ok: [bobPost.Ok] },
+        'Posts by unblocked agents are shown again',
+    );
+
+    /// FILTER LISTS ///
+    // POSITIVE //
+    const filterList = await bob.call('blocklist', 'publish_filter_list', {
+        name: 'Garden spam',
+        agents: [],
+        keywords: ['Tips'],
+        previous: null,
+        utc_unix_time: now,
+    });
+    t.ok(filterList.Ok, 'Filter lists can be published');
+    await s.consistent();
+
+    t.deepEqual(
+        await alice.call('blocklist', 'filter_lists_by', { author: bobAddress }),
+        { Ok: [filterList.Ok] },
+        'Filter lists are linked from their author',
+    );
+
+    t.ok(
+        (await alice.call('blocklist', 'subscribe_filter_list', { address: filterList.Ok, utc_unix_time: now + 3 })).Ok,
+        'Agents can subscribe to filter lists',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'filter_list_subscriptions', {}),
+        { Ok: [filterList.Ok] },
+        'Subscriptions are listed',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [], tags: [1], keywords: ['tips'] } },
+        'Subscribed filter lists are merged with personal blocks',
+    );
+    t.deepEqual(
+        await alice.call('blocklist', 'get_personal_blocks', {}),
+        { Ok: { agents: [], tags: [1], keywords: [] } },
+        'Personal blocks do not include filter lists',
+    );
+
+    t.deepEqual(
+        ((await alice.call('posts', 'search', {
+            query: { type: 'or', values: [{ type: 'exactly', values: 0 }, { type: 'exactly', values: 1 }] },
+            exclude_crossposts: false,
+            apply_filters: true,
+            respect_moderation: false,
+        })).Ok || []).map(result => result.address),
+        [bobPost.Ok],
+        'Search leaves out posts blocked by subscribed filter lists',
+    );
+
+    t.ok(
+        (await bob.call('blocklist', 'publish_filter_list', {
+            name: 'Garden spam',
+            agents: [],
+            keywords: ['mulch'],
+            previous: filterList.Ok,
+            utc_unix_time: now,
+        })).Ok,
+        'New versions of filter lists can be published',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        ((await alice.call('blocklist', 'get_blocks', {})).Ok || {}).keywords,
+        ['mulch'],
+        'Subscribers see the latest version of filter lists',
+    );
+
+    // NEGATIVE //
+    t.ok(
+        (await alice.call('blocklist', 'publish_filter_list', {
+            name: 'Garden spam',
+            agents: [],
+            keywords: [],
+            previous: filterList.Ok,
+            utc_unix_time: now,
+        })).Err,
+        'Filter lists of other agents cannot be changed',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('blocklist', 'publish_filter_list', {
+            name: ' ',
+            agents: [],
+            keywords: [],
+            previous: null,
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Filter list name cannot be empty' },
+        'Filter lists must have a name',
+    );
+
+    /// UNSUBSCRIBING ///
+    t.ok(
+        (await alice.call('blocklist', 'unsubscribe_filter_list', { address: filterList.Ok, utc_unix_time: now + 4 })).Ok,
+        'Agents can unsubscribe from filter lists',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'get_blocks', {}),
+        { Ok: { agents: [], tags: [1], keywords: [] } },
+        'Unsubscribed filter lists no longer apply',
+    );
+
+    t.deepEqual(
+        await alice.call('blocklist', 'unsubscribe_filter_list', { address: filterList.Ok, utc_unix_time: now + 5 }),
+        { Err: { Internal: 'Not subscribed to that filter list' } },
+        'Agents cannot unsubscribe from filter lists they are not subscribed to',
+    );
+});
+
+diorama.registerScenario('Test moderation zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const aliceAddress = await alice.call('posts', 'get_agent_address', {});
+    const alicePost = await alice.call('posts', 'create_post', {
+        post: { title: 'Welcome to the tag', content: '', utc_unix_time: now - 60 },
+        tags: [7],
+    });
+    await s.consistent();
+    const bobPost = await bob.call('posts', 'create_post', {
+        post: { title: 'Off topic', content: '', utc_unix_time: now },
+        tags: [7, 9],
+    });
+    await s.consistent();
+    const searchTag = async (agent, respect_moderation, tag = 7) =>
+        ((await agent.call('posts', 'search', {
+            query: { type: 'exactly', values: tag },
+            exclude_crossposts: false,
+            apply_filters: false,
+            respect_moderation,
+        })).Ok || []).map(result => result.address).sort();
+
+    /// MODERATORS ///
+    // POSITIVE //
+    t.ok(
+        (await alice.call('moderation', 'set_tag_moderators', { tag: 7, moderators: [aliceAddress], utc_unix_time: now })).Ok,
+        'The creator of a tag can propose its moderators',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        await bob.call('moderation', 'tag_moderators', { tag: 7 }),
+        { Ok: { tag: 7, creator: aliceAddress, moderators: [aliceAddress] } },
+        'Tag moderators are the ones proposed by the creator of the tag',
+    );
+
+    t.deepEqual(
+        await bob.call('moderation', 'tag_moderators', { tag: 8 }),
+        { Ok: { tag: 8, creator: null, moderators: [] } },
+        'Tags without posts have no creator or moderators',
+    );
+
+    t.deepEqual(
+        await alice.call('moderation', 'tag_moderators', { tag: 9 }),
+        { Ok: { tag: 9, creator: await bob.call('posts', 'get_agent_address', {}), moderators: [] } },
+        'The first agent to post in a tag is its creator, even if the tag already had posts by others',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        await bob.call('moderation', 'set_tag_moderators', { tag: 7, moderators: [], utc_unix_time: now }),
+        { Err: { Internal: 'Only the creator of a tag can propose its moderators' } },
+        'Only the creator of a tag can propose its moderators',
+    );
+
+    /// FLAGGING ///
+    // POSITIVE //
+    const flag = await alice.call('moderation', 'flag_content', {
+        address: bobPost.Ok,
+        tag: 7,
+        reason: 'Off topic',
+        utc_unix_time: now,
+    });
+    t.ok(flag.Ok, 'Posts can be flagged');
+    t.ok(
+        (await bob.call('moderation', 'flag_content', {
+            address: alicePost.Ok,
+            tag: 7,
+            reason: 'I disagree',
+            utc_unix_time: now,
+        })).Ok,
+        'Anyone can flag posts',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        ((await bob.call('moderation', 'flags', { address: bobPost.Ok })).Ok || []).map(flag => flag.reason),
+        ['Off topic'],
+        'Flags of posts can be listed',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await alice.call('moderation', 'flag_content', {
+            address: bobPost.Ok,
+            tag: 7,
+            reason: ' ',
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Flags must give a reason' },
+        'Flags must give a reason',
+    );
+
+    /// OPTING IN ///
+    t.deepEqual(
+        await searchTag(bob, true),
+        [alicePost.Ok, bobPost.Ok].sort(),
+        'Flags are not honored without opting in',
+    );
+
+    t.ok(
+        (await bob.call('moderation', 'opt_in_moderation', { tag: 7, utc_unix_time: now })).Ok,
+        'Agents can opt in to the moderation of a tag',
+    );
+
+    t.deepEqual(
+        await bob.call('moderation', 'moderated_tags', {}),
+        { Ok: [7] },
+        'Moderated tags are listed',
+    );
+
+    t.deepEqual(
+        await searchTag(bob, true),
+        [alicePost.Ok],
+        'Search leaves out posts flagged by moderators of opted in tags, but not by other agents',
+    );
+    t.deepEqual(
+        await searchTag(bob, true, 9),
+        [bobPost.Ok],
+        'Flags only remove posts from the tag they were flagged in',
+    );
+    t.deepEqual(
+        await searchTag(bob, false),
+        [alicePost.Ok, bobPost.Ok].sort(),
+        'Search can ignore moderation',
+    );
+    t.deepEqual(
+        await searchTag(alice, true),
+        [alicePost.Ok, bobPost.Ok].sort(),
+        'Moderation only applies to agents who opted in',
+    );
+
+    /// OPTING OUT ///
+    t.ok(
+        (await bob.call('moderation', 'opt_out_moderation', { tag: 7, utc_unix_time: now + 1 })).Ok,
+        'Agents can opt out of the moderation of a tag',
+    );
+    t.deepEqual(
+        await searchTag(bob, true),
+        [alicePost.Ok, bobPost.Ok].sort(),
+        'Flags are not honored after opting out',
+    );
+    t.deepEqual(
+        await bob.call('moderation', 'opt_out_moderation', { tag: 7, utc_unix_time: now + 2 }),
+        { Err: { Internal: 'Not opted in to moderation of that tag' } },
+        'Agents cannot opt out of moderation they did not opt in to',
+    );
+});
+
+diorama.registerScenario('Test profiles zome', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const aliceAddress = await alice.call('posts', 'get_agent_address', {});
+    const bobAddress = await bob.call('posts', 'get_agent_address', {});
+
+    /// FALLBACK ///
+    t.deepEqual(
+        await bob.call('profiles', 'get_profile', { agent: aliceAddress }),
+        { Ok: { agent: aliceAddress, display_name: 'alice', bio: '', avatar: null, links: [] } },
+        'Agents without a profile are shown with their nick',
+    );
+
+    t.deepEqual(
+        await bob.call('profiles', 'get_profile', { agent: 'invalid' }),
+        { Err: { Internal: 'Address did not lead to agent id.' } },
+        "Can't get profiles of non-users",
+    );
+
+    /// SETTING ///
+    // POSITIVE //
+    const avatar = await alice.call('files', 'upload_file', {
+        name: 'avatar.png',
+        mime_type: 'image/png',
+        data: Buffer.from('not really a png').toString('base64'),
+    });
+    t.ok(
+        (await alice.call('profiles', 'set_profile', {
+            display_name: 'Alice',
+            bio: 'Posts about gardening',
+            avatar: avatar.Ok,
+            links: ['https://example.com/alice'],
+            utc_unix_time: now,
+        })).Ok,
+        'Agents can set their profile',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        await bob.call('profiles', 'get_profile', { agent: aliceAddress }),
+        {
+            Ok: {
+                agent: aliceAddress,
+                display_name: 'Alice',
+                bio: 'Posts about gardening',
+                avatar: avatar.Ok,
+                links: ['https://example.com/alice'],
+            }
+        },
+        'Profiles can be read by other agents',
+    );
+
+    t.ok(
+        (await alice.call('profiles', 'set_profile', {
+            display_name: 'Alice G.',
+            bio: '',
+            avatar: null,
+            links: [],
+            utc_unix_time: now,
+        })).Ok,
+        'Profiles can be changed',
+    );
+    await s.consistent();
+
+    t.deepEqual(
+        await bob.call('profiles', 'get_profiles', { agents: [bobAddress, aliceAddress] }),
+        {
+            Ok: [
+                { Ok: { agent: bobAddress, display_name: 'bob', bio: '', avatar: null, links: [] } },
+                { Ok: { agent: aliceAddress, display_name: 'Alice G.', bio: '', avatar: null, links: [] } },
+            ]
+        },
+        'Profiles of several agents can be read at once, showing the latest version',
+    );
+
+    t.deepEqual(
+        await bob.call('profiles', 'get_profiles', { agents: ['invalid', bobAddress] }),
+        {
+            Ok: [
+                { Err: { Internal: 'Address did not lead to agent id.' } },
+                { Ok: { agent: bobAddress, display_name: 'bob', bio: '', avatar: null, links: [] } },
+            ]
+        },
+        'Invalid agents do not stop the profiles of others from being read',
+    );
+
+    // NEGATIVE //
+    t.deepEqual(
+        JSON.parse((await bob.call('profiles', 'set_profile', {
+            display_name: ' ',
+            bio: '',
+            avatar: null,
+            links: [],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Display name cannot be empty' },
+        'Display names cannot be empty',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('profiles', 'set_profile', {
+            display_name: 'Bob',
+            bio: '',
+            avatar: null,
+            links: ['ftp://example.com'],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'URL must start with http:// or https://' },
+        'Profile links must be web URLs',
+    );
+
+    t.deepEqual(
+        JSON.parse((await bob.call('profiles', 'set_profile', {
+            display_name: 'Bob',
+            bio: '',
+            avatar: aliceAddress,
+            links: [],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Avatar must be a file manifest' },
+        'Avatars must be files',
+    );
+
+    const notAnImage = await bob.call('files', 'upload_file', {
+        name: 'avatar.txt',
+        mime_type: 'text/plain',
+        data: Buffer.from('not an image').toString('base64'),
+    });
+    t.deepEqual(
+        JSON.parse((await bob.call('profiles', 'set_profile', {
+            display_name: 'Bob',
+            bio: '',
+            avatar: notAnImage.Ok,
+            links: [],
+            utc_unix_time: now,
+        })).Err.Internal).kind,
+        { ValidationFailed: 'Avatar must be an image' },
+        'Avatars must be images',
+    );
+});
+
+diorama.registerScenario('Test user activity', async (s, t, { alice, bob }) => {
+    const now = currentTime();
+    const aliceAddress = await alice.call('posts', 'get_agent_address', {});
+    const bobAddress = await bob.call('posts', 'get_agent_address', {});
+    const post = await alice.call('posts', 'create_post', {
+        post: { title: 'My first post', content: '', utc_unix_time: now - 60 },
+        tags: [0],
+    });
+    await s.consistent();
+    const comment = await alice.call('comments', 'create_comment', {
+        comment: { content: 'My first comment', utc_unix_time: now },
+        target: post.Ok,
+    });
+    await s.consistent();
+    await bob.call('votes', 'vote', {
+        utc_unix_time: now,
+        fraction: 1,
+        in_terms_of: [0],
+        target: post.Ok,
+    });
+    await s.consistent();
+
+    /// USER COMMENTS ///
+    t.deepEqual(
+        await bob.call('comments', 'user_comments', { agent: aliceAddress, page: 0 }),
+        { Ok: [comment.Ok] },
+        'Comments of an agent can be listed',
+    );
+    t.deepEqual(
+        await bob.call('comments', 'user_comments', { agent: aliceAddress, page: 1 }),
+        { Ok: [] },
+        'Comments of an agent are paged',
+    );
+    t.deepEqual(
+        await alice.call('comments', 'user_comments', { agent: bobAddress, page: 0 }),
+        { Ok: [] },
+        'Agents without comments have none listed',
+    );
+
+    /// USER ACTIVITY ///
+    await (async () => {
+        const activity = (await bob.call('posts', 'user_activity', { agent: aliceAddress, page: 0 })).Ok || [];
+        t.deepEqual(
+            activity.map(item => [item.address, item.entry_type]).sort(),
+            [[post.Ok, 'post'], [comment.Ok, 'comment']].sort(),
+            'Activity of an agent includes their posts and comments',
+        );
+        t.ok(
+            activity.length == 2 && activity[0].address == comment.Ok,
+            'Activity is listed newest first',
+        );
+    })();
+
+    t.deepEqual(
+        await bob.call('posts', 'user_activity', { agent: aliceAddress, page: 1 }),
+        { Ok: [] },
+        'Activity of an agent is paged',
+    );
+
+    /// USER STATS ///
+    t.deepEqual(
+        await bob.call('votes', 'user_stats', { agent: aliceAddress }),
+        { Ok: { posts: 1, comments: 1, votes: 1, score: 1000 } },
+        'Stats count posts, comments and votes on them',
+    );
+    t.deepEqual(
+        await alice.call('votes', 'user_stats', { agent: bobAddress }),
+        { Ok: { posts: 0, comments: 0, votes: 0, score: 0 } },
+        'Agents without posts or comments have empty stats',
+    );
+
+    const bobPost = await bob.call('posts', 'create_post', {
+        post: { title: 'Spam', content: '', utc_unix_time: now },
+        tags: [0],
+    });
+    await s.consistent();
+    await alice.call('votes', 'vote', {
+        utc_unix_time: now,
+        fraction: -1,
+        in_terms_of: [0],
+        target: bobPost.Ok,
+    });
+    await s.consistent();
+    t.deepEqual(
+        await alice.call('votes', 'user_stats', { agent: aliceAddress }),
+        { Ok: { posts: 1, comments: 1, votes: 1, score: 0 } },
+        'Stats weight votes by how much the calling agent trusts their voter',
+    );
+    t.deepEqual(
+        await bob.call('votes', 'user_stats', { agent: aliceAddress }),
+        { Ok: { posts: 1, comments: 1, votes: 1, score: 1000 } },
+        'Stats are from the perspective of the calling agent',
+    );
 });
 
 diorama.run();
@@ -0,0 +1,509 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate comet_common;
+
+use hdk::api;
+use hdk::{EntryValidationData, LinkValidationData};
+use hdk::error::{ZomeApiError, ZomeApiResult};
+use hdk::holochain_core_types::{
+    dna::entry_types::Sharing, entry::Entry,
+    time::Iso8601, link::LinkMatch,
+};
+use hdk::holochain_persistence_api::cas::content::Address;
+use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
+use hdk::utils;
+use hdk::ValidationPackageDefinition;
+use comet_common::{latest_address, newest_chain_entries, validate_timestamp, Blockable};
+
+/// Type for tags
+///
+/// Should be exact same as in `posts` zome
+type Tag = u64;
+
+/// Longest keyword that can be blocked, in characters
+const MAX_KEYWORD_LENGTH: usize = 100;
+
+/// Longest name a filter list can have, in characters
+const MAX_LIST_NAME_LENGTH: usize = 100;
+
+/// Agents, tags and keywords an agent does not want to see.
+///
+/// Keywords are matched case-insensitively against the text of posts and
+/// comments as whole words, and are stored in lowercase.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, Default, PartialEq)]
+#[serde(default)]
+struct Blocks {
+    agents: Vec<Address>,
+    tags: Vec<Tag>,
+    keywords: Vec<String>,
+}
+
+impl Blocks {
+    /// Add the agents, tags and keywords of `other` that are not already
+    /// blocked
+    fn extend(&mut self, other: Blocks) {
+        for agent in other.agents {
+            if !self.agents.contains(&agent) {
+                self.agents.push(agent);
+            }
+        }
+        for tag in other.tags {
+            if !self.tags.contains(&tag) {
+                self.tags.push(tag);
+            }
+        }
+        for keyword in other.keywords {
+            if !self.keywords.contains(&keyword) {
+                self.keywords.push(keyword);
+            }
+        }
+    }
+
+    /// Whether `content` is by a blocked agent, in a blocked tag or has a
+    /// blocked keyword in any of its text
+    fn blocks(&self, content: &Blockable) -> bool {
+        self.agents.contains(&content.author)
+            || content.tags.iter().any(|tag| self.tags.contains(tag))
+            || content.texts.iter().any(|text| {
+                let text = text.to_lowercase();
+                self.keywords.iter().any(|keyword| contains_word(&text, keyword))
+            })
+    }
+}
+
+/// Whether `word` appears in `text` as a whole word (or words): not directly
+/// next to another letter or digit. Blocking "ass" doesn't hide "class".
+fn contains_word(text: &str, word: &str) -> bool {
+    if word.is_empty() {
+        return false;
+    }
+    let mut start = 0;
+    while let Some(found) = text[start..].find(word) {
+        let at = start + found;
+        let end = at + word.len();
+        if !text[..at].chars().next_back().map_or(false, char::is_alphanumeric)
+            && !text[end..].chars().next().map_or(false, char::is_alphanumeric)
+        {
+            return true;
+        }
+        start = at + text[at..].chars().next().map_or(1, char::len_utf8);
+    }
+    false
+}
+
+/// Private entry holding a version of an agent's blocks. The latest one on
+/// the agent's chain is the current one.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct Blocklist {
+    blocks: Blocks,
+    /// Addresses of the filter lists the agent is subscribed to
+    #[serde(default)]
+    subscriptions: Vec<Address>,
+    timestamp: Iso8601,
+}
+
+/// Public, named list of agents and keywords, published so other agents can
+/// subscribe to it. New versions are published with `update_entry`.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct FilterList {
+    name: String,
+    agents: Vec<Address>,
+    keywords: Vec<String>,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// Returns `Ok(())` if every blocked keyword is non-empty, lowercase and
+/// short enough
+fn validate_keywords(keywords: &[String]) -> Result<(), String> {
+    for keyword in keywords {
+        if keyword.trim().is_empty() {
+            return Err("Blocked keywords cannot be empty".to_string());
+        }
+        if keyword.chars().count() > MAX_KEYWORD_LENGTH {
+            return Err(format!(
+                "Blocked keywords must be at most {} characters",
+                MAX_KEYWORD_LENGTH
+            ));
+        }
+        if keyword != &keyword.to_lowercase() {
+            return Err("Blocked keywords must be lowercase".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if a filter list has a name of the right length and
+/// valid keywords
+fn validate_filter_list(filter_list: &FilterList) -> Result<(), String> {
+    if filter_list.name.trim().is_empty() {
+        return Err("Filter list name cannot be empty".to_string());
+    }
+    if filter_list.name.chars().count() > MAX_LIST_NAME_LENGTH {
+        return Err(format!(
+            "Filter list name must be at most {} characters",
+            MAX_LIST_NAME_LENGTH
+        ));
+    }
+    validate_keywords(&filter_list.keywords)
+}
+
+/// Trim and lowercase keywords, dropping duplicates
+fn normalize_keywords(keywords: Vec<String>) -> Vec<String> {
+    let mut normalized: Vec<String> = Vec::new();
+    for keyword in keywords {
+        let keyword = keyword.trim().to_lowercase();
+        if !normalized.contains(&keyword) {
+            normalized.push(keyword);
+        }
+    }
+    normalized
+}
+
+/// Get this agent's latest blocklist entry, if it has ever blocked anything
+/// or subscribed to a filter list
+fn latest_blocklist() -> ZomeApiResult<Option<Blocklist>> {
+    Ok(newest_chain_entries::<Blocklist>("blocklist")?
+        .into_iter()
+        .next()
+        .map(|(_, blocklist)| blocklist))
+}
+
+/// Get this agent's own blocks and filter list subscriptions
+fn personal_blocklist() -> ZomeApiResult<(Blocks, Vec<Address>)> {
+    Ok(latest_blocklist()?
+        .map(|blocklist| (blocklist.blocks, blocklist.subscriptions))
+        .unwrap_or_default())
+}
+
+/// Get everything this agent has blocked: its own blocks merged with the
+/// latest version of every filter list it is subscribed to.
+///
+/// Filter lists that can't be found are skipped.
+fn handle_get_blocks() -> ZomeApiResult<Blocks> {
+    let (mut blocks, subscriptions) = personal_blocklist()?;
+    for address in subscriptions {
+        if let Ok(filter_list) = utils::get_as_type::<FilterList>(address) {
+            blocks.extend(Blocks {
+                agents: filter_list.agents,
+                tags: Vec::new(),
+                keywords: filter_list.keywords,
+            });
+        }
+    }
+    Ok(blocks)
+}
+
+/// For each of `items`, whether it is hidden by this agent's blocks (see
+/// `handle_get_blocks`). Keywords only match whole words.
+fn handle_is_blocked(items: Vec<Blockable>) -> ZomeApiResult<Vec<bool>> {
+    let blocks = handle_get_blocks()?;
+    Ok(items.iter().map(|content| blocks.blocks(content)).collect())
+}
+
+/// Get the blocks this agent made itself, without its filter lists
+fn handle_get_personal_blocks() -> ZomeApiResult<Blocks> {
+    Ok(personal_blocklist()?.0)
+}
+
+/// Commit a new version of this agent's blocks and subscriptions
+fn commit_blocklist(blocks: Blocks, subscriptions: Vec<Address>, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let blocklist = Blocklist {
+        blocks,
+        subscriptions,
+        timestamp: utc_unix_time.into(),
+    };
+    api::commit_entry(&Entry::App("blocklist".into(), blocklist.into()))
+}
+
+/// Add agents, tags and keywords to this agent's blocks
+fn handle_block(blocks: Blocks, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let (mut current, subscriptions) = personal_blocklist()?;
+    current.extend(Blocks {
+        keywords: normalize_keywords(blocks.keywords),
+        ..blocks
+    });
+    commit_blocklist(current, subscriptions, utc_unix_time)
+}
+
+/// Remove agents, tags and keywords from this agent's blocks
+fn handle_unblock(blocks: Blocks, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let (mut current, subscriptions) = personal_blocklist()?;
+    let keywords = normalize_keywords(blocks.keywords);
+    current.agents.retain(|agent| !blocks.agents.contains(agent));
+    current.tags.retain(|tag| !blocks.tags.contains(tag));
+    current.keywords.retain(|keyword| !keywords.contains(keyword));
+    commit_blocklist(current, subscriptions, utc_unix_time)
+}
+
+/// Publish a filter list, or a new version of the filter list at `previous`
+///
+/// Returns the address of the published version. Subscribers always see the
+/// latest version, whichever version they subscribed with.
+fn handle_publish_filter_list(
+    name: String,
+    agents: Vec<Address>,
+    keywords: Vec<String>,
+    previous: Option<Address>,
+    utc_unix_time: u64,
+) -> ZomeApiResult<Address> {
+    let filter_list = Entry::App(
+        "filter_list".into(),
+        FilterList {
+            name: name.trim().to_owned(),
+            agents,
+            keywords: normalize_keywords(keywords),
+            key_hash: api::AGENT_ADDRESS.clone(),
+            timestamp: utc_unix_time.into(),
+        }
+        .into(),
+    );
+    match previous {
+        Some(previous) => {
+            let latest = latest_address(&previous)?
+                .ok_or_else(|| ZomeApiError::Internal("Filter list not found".to_string()))?;
+            api::update_entry(filter_list, &latest)
+        }
+        None => {
+            let address = api::commit_entry(&filter_list)?;
+            api::link_entries(&api::AGENT_ADDRESS, &address, "filter_list_author", "")?;
+            Ok(address)
+        }
+    }
+}
+
+/// Get the latest version of the filter list at `address`
+fn handle_get_filter_list(address: Address) -> ZomeApiResult<FilterList> {
+    utils::get_as_type(address)
+}
+
+/// Get the addresses of the filter lists published by `author`
+fn handle_filter_lists_by(author: Address) -> ZomeApiResult<Vec<Address>> {
+    Ok(api::get_links(&author, LinkMatch::Exactly("filter_list_author"), LinkMatch::Any)?.addresses())
+}
+
+/// Get the addresses of the filter lists this agent is subscribed to
+fn handle_filter_list_subscriptions() -> ZomeApiResult<Vec<Address>> {
+    Ok(personal_blocklist()?.1)
+}
+
+/// Subscribe to the filter list at `address`, blocking its agents and
+/// keywords alongside this agent's own blocks
+fn handle_subscribe_filter_list(address: Address, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    utils::get_as_type::<FilterList>(address.clone())?;
+    let (blocks, mut subscriptions) = personal_blocklist()?;
+    if !subscriptions.contains(&address) {
+        subscriptions.push(address);
+    }
+    commit_blocklist(blocks, subscriptions, utc_unix_time)
+}
+
+/// Unsubscribe from the filter list at `address`
+fn handle_unsubscribe_filter_list(address: Address, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let (blocks, mut subscriptions) = personal_blocklist()?;
+    if !subscriptions.contains(&address) {
+        return Err(ZomeApiError::Internal("Not subscribed to that filter list".to_string()));
+    }
+    subscriptions.retain(|subscription| subscription != &address);
+    commit_blocklist(blocks, subscriptions, utc_unix_time)
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "blocklist",
+            description: "Private list of the agents, tags and keywords an agent has blocked",
+            sharing: Sharing::Private,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<Blocklist>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: blocklist,
+                        validation_data,
+                    } => {
+                        validate_keywords(&blocklist.blocks.keywords)?;
+                        validate_timestamp(&blocklist.timestamp, &validation_data.package.chain_header)
+                    }
+                    _ => Err("Blocklists cannot be changed, block or unblock again".to_string()),
+                }
+            }
+        ),
+        entry!(
+            name: "filter_list",
+            description: "Public, named list of agents and keywords that other agents can subscribe to",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<FilterList>| {
+                let not_ok = Err("Cannot alter filter list that is not yours.".to_string());
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: filter_list,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if provenances.iter().all(|provenance| provenance.0 == filter_list.key_hash) {
+                            validate_filter_list(&filter_list)?;
+                            validate_timestamp(&filter_list.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            not_ok
+                        }
+                    }
+                    EntryValidationData::Modify {
+                        new_entry: new_filter_list,
+                        old_entry: old_filter_list,
+                        old_entry_header,
+                        validation_data,
+                    } => {
+                        let mut provenances = validation_data.package.chain_header.provenances()
+                            .iter()
+                            .chain(old_entry_header.provenances());
+                        if old_filter_list.key_hash == new_filter_list.key_hash
+                            && provenances.all(|provenance| provenance.0 == old_filter_list.key_hash)
+                        {
+                            validate_filter_list(&new_filter_list)?;
+                            validate_timestamp(&new_filter_list.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            not_ok
+                        }
+                    }
+                    EntryValidationData::Delete {
+                        old_entry: old_filter_list,
+                        old_entry_header,
+                        validation_data,
+                    } => {
+                        let mut provenances = validation_data.package.chain_header.provenances()
+                            .iter()
+                            .chain(old_entry_header.provenances());
+                        if provenances.all(|provenance| provenance.0 == old_filter_list.key_hash) {
+                            Ok(())
+                        } else {
+                            not_ok
+                        }
+                    }
+                }
+            },
+            links: [
+                // Filter lists link from (to implicit by `key_hash` field) their author's key hash
+                from!(
+                    "%agent_id",
+                    link_type: "filter_list_author",
+                    validation_package: || hdk::ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| &provenance.0 == link.link().base()) {
+                            return Err("Cannot link to filter list from author not in `key_hash`".to_owned());
+                        }
+                        match utils::get_as_type::<FilterList>(link.link().target().clone()) {
+                            Ok(filter_list) => {
+                                if &filter_list.key_hash == link.link().base() {
+                                    Ok(())
+                                } else {
+                                    Err("Cannot link to filter list from author not in `key_hash`".to_owned())
+                                }
+                            },
+                            Err(_) => Err("Link was not filter list".to_owned())
+                        }
+                    }
+                )
+            ]
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        block: {
+            inputs: |blocks: Blocks, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_block
+        }
+        unblock: {
+            inputs: |blocks: Blocks, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_unblock
+        }
+        get_blocks: {
+            inputs: | |,
+            outputs: |result: ZomeApiResult<Blocks>|,
+            handler: handle_get_blocks
+        }
+        is_blocked: {
+            inputs: |items: Vec<Blockable>|,
+            outputs: |result: ZomeApiResult<Vec<bool>>|,
+            handler: handle_is_blocked
+        }
+        get_personal_blocks: {
+            inputs: | |,
+            outputs: |result: ZomeApiResult<Blocks>|,
+            handler: handle_get_personal_blocks
+        }
+        publish_filter_list: {
+            inputs: |name: String, agents: Vec<Address>, keywords: Vec<String>, previous: Option<Address>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_publish_filter_list
+        }
+        get_filter_list: {
+            inputs: |address: Address|,
+            outputs: |result: ZomeApiResult<FilterList>|,
+            handler: handle_get_filter_list
+        }
+        filter_lists_by: {
+            inputs: |author: Address|,
+            outputs: |result: ZomeApiResult<Vec<Address>>|,
+            handler: handle_filter_lists_by
+        }
+        subscribe_filter_list: {
+            inputs: |address: Address, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_subscribe_filter_list
+        }
+        unsubscribe_filter_list: {
+            inputs: |address: Address, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_unsubscribe_filter_list
+        }
+        filter_list_subscriptions: {
+            inputs: | |,
+            outputs: |result: ZomeApiResult<Vec<Address>>|,
+            handler: handle_filter_list_subscriptions
+        }
+    ]
+
+    traits: {
+        hc_public [
+            block,
+            unblock,
+            get_blocks,
+            is_blocked,
+            get_personal_blocks,
+            publish_filter_list,
+            get_filter_list,
+            filter_lists_by,
+            subscribe_filter_list,
+            unsubscribe_filter_list,
+            filter_list_subscriptions
+        ]
+    }
+}
@@ -6,6 +6,7 @@ extern crate serde_derive;
 extern crate serde_json;
 #[macro_use]
 extern crate holochain_json_derive;
+extern crate comet_common;
 
 use hdk::utils;
 use hdk::{
@@ -15,11 +16,20 @@ use hdk::{
     EntryValidationData,
     LinkValidationData,
     holochain_core_types::{
-        dna::entry_types::Sharing, entry::Entry,
+        dna::entry_types::Sharing, entry::Entry, chain_header::ChainHeader,
         time::Iso8601, validation::ValidationPackageDefinition, link::LinkMatch
     },
     holochain_persistence_api::cas::content::Address,
 };
+use comet_common::{
+    author_link_tag, authored_page, blocked, get_history_with_headers, get_latest_with_header, root_address,
+    validate_attachments, validate_timestamp, Blockable, CometProperties,
+};
+
+/// Type for tags
+///
+/// Should be exact same as in `posts` zome
+type Tag = u64;
 
 /// Represents a users comment
 #[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
@@ -32,8 +42,48 @@ struct Comment {
     /// Time of the post creation.
     /// They are used to avoid accidental hash collisions.
     ///
-    /// *Should not be used as real timestamp.*
+    /// Validated to be within `TIMESTAMP_TOLERANCE` of the chain header, but
+    /// `TimedComment.header_timestamp` should be preferred.
     timestamp: Iso8601,
+    /// Whether this comment has been replaced by a tombstone. The content of
+    /// deleted comments is `DELETED_TEXT`.
+    ///
+    /// Left out when `false`, so comments from before tombstones still
+    /// deserialize and keep their addresses.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+    /// Addresses of the manifests of files (from the `files` zome) attached
+    /// to this comment
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Address>,
+}
+
+/// Text that replaces the content of deleted comments
+const DELETED_TEXT: &str = "[deleted]";
+
+/// How many comments are returned per page of an agent's comments
+const PAGE_SIZE: usize = 25;
+
+/// A comment along with the time it was committed and who committed it,
+/// taken from its chain header
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct TimedComment {
+    comment: Comment,
+    /// Address of this version of the comment
+    address: Address,
+    header_timestamp: Iso8601,
+    author: Address,
+    /// Whether this version replaced an earlier one
+    edited: bool,
+}
+
+/// The latest version of a comment, along with whether it has been edited
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct LatestComment {
+    #[serde(flatten)]
+    comment: Comment,
+    /// Whether this version replaced an earlier one
+    edited: bool,
 }
 
 /// The type of comment that the "client" will give the "server".
@@ -42,6 +92,8 @@ struct Comment {
 struct CommentContent {
     content: String,
     utc_unix_time: u64,
+    #[serde(default)]
+    attachments: Vec<Address>,
 }
 
 impl Into<Comment> for CommentContent {
@@ -50,6 +102,8 @@ impl Into<Comment> for CommentContent {
             content: self.content,
             key_hash: api::AGENT_ADDRESS.clone(),
             timestamp: self.utc_unix_time.into(),
+            deleted: false,
+            attachments: self.attachments,
         }
     }
 }
@@ -69,6 +123,7 @@ fn handle_create_comment(comment: CommentContent, target: Address) -> ZomeApiRes
 /// Create a post given a full `Post` struct, including `timestamp` and
 /// `key_hash`
 fn handle_create_comment_raw(comment: Comment, target: Address) -> ZomeApiResult<Address> {
+    let target = root_address(&target)?;
     let target_type = match api::get_entry(&target)? {
         Some(Entry::App(t, _)) => t,
         _ => return Err(ZomeApiError::Internal("Comment target was not app entry.".to_string())),
@@ -80,31 +135,143 @@ fn handle_create_comment_raw(comment: Comment, target: Address) -> ZomeApiResult
         _ => return Err(ZomeApiError::Internal("Comment target was not post or comment entry.".to_string())),
     };
 
+    let author_tag = author_link_tag(&comment.timestamp).map_err(ZomeApiError::Internal)?;
     let comment_entry = Entry::App("comment".into(), comment.into());
     let comment_address = api::commit_entry(&comment_entry)?;
     utils::link_entries_bidir(&target, &comment_address, link1type, link2type, "", "")?;
     // Link from author
-    api::link_entries(&api::AGENT_ADDRESS, &comment_address, "comment_author", "")?;
+    api::link_entries(&api::AGENT_ADDRESS, &comment_address, "comment_author", &author_tag)?;
     Ok(comment_address)
 }
 
-/// Read a comment
-fn handle_read_comment(address: Address) -> ZomeApiResult<Comment> {
-    utils::get_as_type(address)
+/// Read the latest version of a comment, with whether it has been edited
+fn handle_read_comment(address: Address) -> ZomeApiResult<LatestComment> {
+    let timed_comment = handle_read_timed_comment(address)?;
+    Ok(LatestComment {
+        comment: timed_comment.comment,
+        edited: timed_comment.edited,
+    })
+}
+
+/// Build a `TimedComment` from a comment entry and its chain header
+fn to_timed_comment(address: Address, entry: Entry, header: ChainHeader) -> ZomeApiResult<TimedComment> {
+    let comment = match entry {
+        Entry::App(_, value) => serde_json::from_str::<Comment>(&Into::<String>::into(value))
+            .map_err(|_| ZomeApiError::Internal("Entry was not a comment".to_string()))?,
+        _ => return Err(ZomeApiError::Internal("Entry was not a comment".to_string())),
+    };
+    let author = header
+        .provenances()
+        .first()
+        .map(|provenance| provenance.0.clone())
+        .unwrap_or_else(|| comment.key_hash.clone());
+    Ok(TimedComment {
+        comment,
+        address,
+        header_timestamp: header.timestamp().clone(),
+        author,
+        edited: header.link_update_delete().is_some(),
+    })
+}
+
+/// Read a comment along with its header timestamp and author
+fn handle_read_timed_comment(address: Address) -> ZomeApiResult<TimedComment> {
+    match get_latest_with_header(&address)? {
+        Some((address, entry, header)) => to_timed_comment(address, entry, header),
+        None => Err(ZomeApiError::Internal("No entry at this address".to_string())),
+    }
+}
+
+/// Get every revision of a comment, oldest first
+///
+/// Once a comment has been replaced by a tombstone every earlier revision is
+/// redacted as well.
+fn handle_comment_history(address: Address) -> ZomeApiResult<Vec<TimedComment>> {
+    let mut history = get_history_with_headers(&root_address(&address)?)?
+        .into_iter()
+        .map(|(address, entry, header)| to_timed_comment(address, entry, header))
+        .collect::<ZomeApiResult<Vec<TimedComment>>>()?;
+    if history.iter().any(|timed_comment| timed_comment.comment.deleted) {
+        for timed_comment in &mut history {
+            timed_comment.comment.content = DELETED_TEXT.to_owned();
+            timed_comment.comment.attachments.clear();
+        }
+    }
+    Ok(history)
+}
+
+/// Returns `Ok(())` if a comment marked `deleted` is a proper tombstone,
+/// with nothing of the comment it replaced left in it
+fn validate_tombstone(comment: &Comment) -> Result<(), String> {
+    if !comment.deleted {
+        Ok(())
+    } else if comment.content != DELETED_TEXT {
+        Err(format!("Deleted comments must have {} as their content", DELETED_TEXT))
+    } else if !comment.attachments.is_empty() {
+        Err("Deleted comments cannot have attachments".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if the content of a comment is within the length set in
+/// the DNA properties and has no control characters other than line breaks
+/// and tabs
+fn validate_comment_text(comment: &Comment) -> Result<(), String> {
+    let max_length = CometProperties::load()?.max_comment_length;
+    if comment.content.chars().count() > max_length {
+        Err(format!("Comment must be at most {} characters", max_length))
+    } else if comment
+        .content
+        .chars()
+        .any(|c| c.is_control() && c != '\n' && c != '\r' && c != '\t')
+    {
+        Err("Comment cannot contain control characters".to_string())
+    } else {
+        Ok(())
+    }
 }
 
-/// Update a comment at address `old_address` with the entry `new_entry`
+/// Update the comment at `old_address` with the entry `new_entry`
+///
+/// `old_address` can be the address of any version of the comment, the
+/// latest version is the one replaced.
 fn handle_update_comment(
     old_address: Address,
     new_entry: CommentContent,
 ) -> ZomeApiResult<Address> {
-    let new_entry: Comment = new_entry.into();
-    let new_comment_entry = Entry::App("comment".into(), new_entry.into());
-    api::update_entry(new_comment_entry, &old_address)
+    replace_comment(old_address, new_entry.into())
+}
+
+/// Replace the latest version of the comment at `old_address` with
+/// `new_comment`
+fn replace_comment(old_address: Address, new_comment: Comment) -> ZomeApiResult<Address> {
+    let latest_address = match get_latest_with_header(&old_address)? {
+        Some((latest_address, _, _)) => latest_address,
+        None => old_address,
+    };
+    let new_comment_entry = Entry::App("comment".into(), new_comment.into());
+    api::update_entry(new_comment_entry, &latest_address)
+}
+
+/// Replace a comment with a tombstone, hiding its content while keeping it
+/// and its replies reachable
+fn handle_tombstone_comment(address: Address, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    replace_comment(
+        address,
+        Comment {
+            content: DELETED_TEXT.to_owned(),
+            key_hash: api::AGENT_ADDRESS.clone(),
+            timestamp: utc_unix_time.into(),
+            deleted: true,
+            attachments: Vec::new(),
+        },
+    )
 }
 
 /// Delete a comment
 fn handle_delete_comment(address: Address) -> ZomeApiResult<Address> {
+    let address = root_address(&address)?;
     for post_parent in api::get_links(&address, LinkMatch::Exactly("child_of_post"), LinkMatch::Any)?.addresses() {
         api::remove_link(
             &post_parent,
@@ -125,8 +292,46 @@ fn handle_delete_comment(address: Address) -> ZomeApiResult<Address> {
 }
 
 /// Return the addresses of entries linked by "comment"
-fn handle_comments_from_address(address: Address) -> ZomeApiResult<Vec<Address>> {
-    Ok(api::get_links(&address, LinkMatch::Regex("comment_on_\\w+"), LinkMatch::Any)?.addresses().clone())
+///
+/// `address` can be the address of any version of a post or comment.
+///
+/// When `apply_filters` is set, comments by agents blocked by the calling
+/// agent or containing keywords they blocked are left out.
+fn handle_comments_from_address(address: Address, apply_filters: bool) -> ZomeApiResult<Vec<Address>> {
+    let comments = api::get_links(&root_address(&address)?, LinkMatch::Regex("comment_on_\\w+"), LinkMatch::Any)?.addresses();
+    if !apply_filters {
+        return Ok(comments);
+    }
+    let comments: Vec<(Address, Option<Blockable>)> = comments
+        .into_iter()
+        .map(|address| {
+            let item = utils::get_as_type::<Comment>(address.clone())
+                .ok()
+                .map(|comment| Blockable {
+                    author: comment.key_hash,
+                    tags: Vec::new(),
+                    texts: vec![comment.content],
+                });
+            (address, item)
+        })
+        .collect();
+    let mut is_blocked = blocked(comments.iter().filter_map(|(_, item)| item.clone()).collect())?.into_iter();
+    Ok(comments
+        .into_iter()
+        .filter(|(_, item)| item.is_none() || !is_blocked.next().unwrap_or(false))
+        .map(|(address, _)| address)
+        .collect())
+}
+
+/// Get a page of the comments made by `agent`, newest first. Pages start at
+/// 0 and hold `PAGE_SIZE` comments.
+///
+/// These are the addresses of the first version of each comment.
+fn handle_user_comments(agent: Address, page: u32) -> ZomeApiResult<Vec<Address>> {
+    Ok(authored_page(&agent, &["comment_author"], page, PAGE_SIZE)?
+        .into_iter()
+        .map(|(_, address)| address)
+        .collect())
 }
 
 define_zome! {
@@ -146,7 +351,12 @@ define_zome! {
                     } => {
                         let provenances = validation_data.package.chain_header.provenances();
                         if provenances.iter().all(|provenance| provenance.0 == comment.key_hash) {
-                            Ok(())
+                            if comment.deleted {
+                                return Err("Comments cannot be created deleted.".to_string());
+                            }
+                            validate_comment_text(&comment)?;
+                            validate_attachments(&comment.attachments)?;
+                            validate_timestamp(&comment.timestamp, &validation_data.package.chain_header)
                         } else {
                             not_ok
                         }
@@ -163,7 +373,13 @@ define_zome! {
                         if old_comment.key_hash == new_comment.key_hash
                             && provenances.all(|provenance| provenance.0 == old_comment.key_hash)
                         {
-                            Ok(())
+                            if old_comment.deleted {
+                                return Err("Cannot alter comment that has been deleted.".to_string());
+                            }
+                            validate_tombstone(&new_comment)?;
+                            validate_comment_text(&new_comment)?;
+                            validate_attachments(&new_comment.attachments)?;
+                            validate_timestamp(&new_comment.timestamp, &validation_data.package.chain_header)
                         } else {
                             not_ok
                         }
@@ -268,22 +484,24 @@ define_zome! {
                     link_type: "comment_author",
                     validation_package: || hdk::ValidationPackageDefinition::Entry,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let link = match link_validation_data {
+                        let (link, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => link,
+                            } => (link, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => link,
+                            } => (link, false),
                         };
                         match utils::get_as_type::<Comment>(link.link().target().clone()) {
                             Ok(comment) => {
-                                if &comment.key_hash == link.link().base() {
-                                    Ok(())
-                                } else {
+                                if &comment.key_hash != link.link().base() {
                                     Err("Cannot link to comment from author not in `key_hash`".to_owned())
+                                } else if adding && link.link().tag() != &author_link_tag(&comment.timestamp)? {
+                                    Err("Author links must be tagged with the time of the comment".to_owned())
+                                } else {
+                                    Ok(())
                                 }
                             },
                             Err(_) => Err("Link was not comment".to_owned())
@@ -313,33 +531,57 @@ define_zome! {
         }
         read_comment: {
             inputs: |address: Address|,
-            outputs: |comment: ZomeApiResult<Comment>|,
+            outputs: |comment: ZomeApiResult<LatestComment>|,
             handler: handle_read_comment
         }
+        read_timed_comment: {
+            inputs: |address: Address|,
+            outputs: |comment: ZomeApiResult<TimedComment>|,
+            handler: handle_read_timed_comment
+        }
+        comment_history: {
+            inputs: |address: Address|,
+            outputs: |history: ZomeApiResult<Vec<TimedComment>>|,
+            handler: handle_comment_history
+        }
         update_comment: {
             inputs: |old_address: Address, new_entry: CommentContent|,
             outputs: |new_comment: ZomeApiResult<Address>|,
             handler: handle_update_comment
         }
+        tombstone_comment: {
+            inputs: |address: Address, utc_unix_time: u64|,
+            outputs: |new_comment: ZomeApiResult<Address>|,
+            handler: handle_tombstone_comment
+        }
         delete_comment: {
             inputs: |address: Address|,
             outputs: |result: ZomeApiResult<Address>|,
             handler: handle_delete_comment
         }
         comments_from_address: {
-            inputs: |address: Address|,
+            inputs: |address: Address, apply_filters: bool|,
             outputs: |comments: ZomeApiResult<Vec<Address>>|,
             handler: handle_comments_from_address
         }
+        user_comments: {
+            inputs: |agent: Address, page: u32|,
+            outputs: |comments: ZomeApiResult<Vec<Address>>|,
+            handler: handle_user_comments
+        }
     ]
 
     traits: {
         hc_public [
             create_comment,
             read_comment,
+            read_timed_comment,
+            comment_history,
             update_comment,
+            tombstone_comment,
             delete_comment,
-            comments_from_address
+            comments_from_address,
+            user_comments
         ]
     }
 }
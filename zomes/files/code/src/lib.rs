@@ -0,0 +1,214 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate base64;
+extern crate sha2;
+
+use hdk::utils;
+use hdk::{
+    api,
+    holochain_json_api::{ json::JsonString, error::JsonError },
+    error::{ZomeApiError, ZomeApiResult},
+    holochain_core_types::{
+        dna::entry_types::Sharing, entry::Entry,
+        validation::ValidationPackageDefinition,
+    },
+    holochain_persistence_api::cas::content::Address,
+    EntryValidationData,
+};
+use sha2::{Digest, Sha256};
+
+/// Largest amount of data in a single chunk, in bytes
+const MAX_CHUNK_SIZE: usize = 256 * 1024;
+
+/// Largest file that can be uploaded, in bytes
+const MAX_FILE_SIZE: u64 = 16 * 1024 * 1024;
+
+/// Longest mime type or file name allowed in a manifest, in bytes
+const MAX_METADATA_LENGTH: usize = 255;
+
+/// A piece of a file.
+///
+/// Chunks are addressed by their content, so identical chunks are only
+/// stored once no matter how many files they are part of.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct Chunk {
+    /// Base64 encoded data of this chunk
+    data: String,
+}
+
+/// Describes a file and where its data is stored.
+///
+/// Posts and comments reference files by the address of their manifest.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct FileManifest {
+    name: String,
+    mime_type: String,
+    /// Size of the file, in bytes
+    size: u64,
+    /// Hex encoded SHA-256 hash of the whole file
+    hash: String,
+    /// Addresses of the chunks of the file, in order
+    chunks: Vec<Address>,
+}
+
+/// Hex encoded SHA-256 hash of `data`
+fn sha256_hex(data: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.input(data);
+    format!("{:x}", hasher.result())
+}
+
+/// Store a file as chunks and a manifest, returning the address of the
+/// manifest
+///
+/// `data` is the base64 encoded contents of the file.
+fn handle_upload_file(name: String, mime_type: String, data: String) -> ZomeApiResult<Address> {
+    let data = base64::decode(&data)
+        .map_err(|_| ZomeApiError::Internal("File data is not valid base64".to_string()))?;
+    if data.len() as u64 > MAX_FILE_SIZE {
+        return Err(ZomeApiError::Internal(format!(
+            "Files must be at most {} bytes",
+            MAX_FILE_SIZE
+        )));
+    }
+
+    let mut chunks = Vec::new();
+    for chunk_data in data.chunks(MAX_CHUNK_SIZE) {
+        let chunk = Chunk {
+            data: base64::encode(chunk_data),
+        };
+        chunks.push(api::commit_entry(&Entry::App("chunk".into(), chunk.into()))?);
+    }
+
+    let manifest = FileManifest {
+        name,
+        mime_type,
+        size: data.len() as u64,
+        hash: sha256_hex(&data),
+        chunks,
+    };
+    api::commit_entry(&Entry::App("manifest".into(), manifest.into()))
+}
+
+/// Get the manifest of a file
+fn handle_get_file_manifest(address: Address) -> ZomeApiResult<FileManifest> {
+    utils::get_as_type(address)
+}
+
+/// Get a chunk of a file
+fn handle_get_chunk(address: Address) -> ZomeApiResult<Chunk> {
+    utils::get_as_type(address)
+}
+
+/// Returns `Ok(())` if a chunk holds valid data of an allowed size
+fn validate_chunk(chunk: &Chunk) -> Result<(), String> {
+    let data = base64::decode(&chunk.data).map_err(|_| "Chunk data is not valid base64".to_string())?;
+    if data.is_empty() {
+        Err("Chunks cannot be empty".to_string())
+    } else if data.len() > MAX_CHUNK_SIZE {
+        Err(format!("Chunks must be at most {} bytes", MAX_CHUNK_SIZE))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if a manifest's chunks exist and add up to a file with
+/// the size and hash it claims
+fn validate_manifest(manifest: &FileManifest) -> Result<(), String> {
+    if manifest.size > MAX_FILE_SIZE {
+        return Err(format!("Files must be at most {} bytes", MAX_FILE_SIZE));
+    }
+    if manifest.name.len() > MAX_METADATA_LENGTH || manifest.mime_type.len() > MAX_METADATA_LENGTH {
+        return Err(format!(
+            "File names and mime types must be at most {} bytes",
+            MAX_METADATA_LENGTH
+        ));
+    }
+    let mut data = Vec::new();
+    for chunk_address in &manifest.chunks {
+        let chunk: Chunk = utils::get_as_type(chunk_address.clone())
+            .map_err(|_| "Manifest refers to a chunk that could not be found".to_string())?;
+        let chunk_data = base64::decode(&chunk.data)
+            .map_err(|_| "Chunk data is not valid base64".to_string())?;
+        data.extend(chunk_data);
+        if data.len() as u64 > manifest.size {
+            return Err("File is bigger than the size in its manifest".to_string());
+        }
+    }
+    if data.len() as u64 != manifest.size {
+        Err("File is smaller than the size in its manifest".to_string())
+    } else if sha256_hex(&data) != manifest.hash {
+        Err("File does not match the hash in its manifest".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "chunk",
+            description: "A piece of an uploaded file",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<Chunk>| {
+                match entry_validation_data {
+                    EntryValidationData::Create { entry: chunk, .. } => validate_chunk(&chunk),
+                    _ => Err("Chunks are read-only".to_owned()),
+                }
+            }
+        ),
+        entry!(
+            name: "manifest",
+            description: "Describes an uploaded file and the chunks it is made of",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<FileManifest>| {
+                match entry_validation_data {
+                    EntryValidationData::Create { entry: manifest, .. } => validate_manifest(&manifest),
+                    _ => Err("Manifests are read-only".to_owned()),
+                }
+            }
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        upload_file: {
+            inputs: |name: String, mime_type: String, data: String|,
+            outputs: |manifest_address: ZomeApiResult<Address>|,
+            handler: handle_upload_file
+        }
+        get_file_manifest: {
+            inputs: |address: Address|,
+            outputs: |manifest: ZomeApiResult<FileManifest>|,
+            handler: handle_get_file_manifest
+        }
+        get_chunk: {
+            inputs: |address: Address|,
+            outputs: |chunk: ZomeApiResult<Chunk>|,
+            handler: handle_get_chunk
+        }
+    ]
+
+    traits: {
+        hc_public [
+            upload_file,
+            get_file_manifest,
+            get_chunk
+        ]
+    }
+}
@@ -0,0 +1,577 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate comet_common;
+
+use hdk::api;
+use hdk::{EntryValidationData, LinkValidationData};
+use hdk::error::{ZomeApiError, ZomeApiResult};
+use hdk::holochain_core_types::{
+    chain_header::ChainHeader, dna::entry_types::Sharing, entry::Entry,
+    time::Iso8601, link::LinkMatch,
+};
+use hdk::holochain_persistence_api::cas::content::Address;
+use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
+use hdk::utils;
+use hdk::ValidationPackageDefinition;
+use comet_common::{created_at, newest_chain_entries, root_address, unix_time, validate_timestamp};
+use std::collections::HashMap;
+
+/// Type for tags
+///
+/// Should be exact same as in `posts` zome
+type Tag = u64;
+
+/// Anchor
+///
+/// Should be exact same as in `anchors` zome
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct Anchor {
+    anchor_type: String,
+    anchor_text: String,
+}
+
+/// Most moderators a tag can have
+const MAX_MODERATORS: usize = 32;
+
+/// Longest reason a flag can give, in characters
+const MAX_REASON_LENGTH: usize = 500;
+
+/// Claim to be the creator of a tag, linked from the tag's anchor. Made by
+/// the `posts` zome along with the first post in a tag, and validated there
+/// to be made with a post by the claimant.
+///
+/// Should be exact same as in `posts` zome
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct TagCreator {
+    tag: Tag,
+    /// The post the tag was claimed with
+    post: Address,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// Moderators proposed for a tag.
+///
+/// Anyone can commit these, but only the latest one by the tag's creator
+/// counts.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct TagModerators {
+    tag: Tag,
+    moderators: Vec<Address>,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// Current moderation of a tag
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct TagModeration {
+    tag: Tag,
+    /// Agent who claimed the tag first, if anyone has
+    creator: Option<Address>,
+    moderators: Vec<Address>,
+}
+
+/// Request to remove a post or comment from a tag.
+///
+/// Flags are only honored by agents who opted in to the moderation of `tag`,
+/// only while the flagger is one of its moderators, and only where the
+/// target is being shown in `tag`.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct Flag {
+    target: Address,
+    tag: Tag,
+    reason: String,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// A post or comment along with the tags it is being shown in, such as the
+/// tags a search found it in
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct ShownIn {
+    address: Address,
+    tags: Vec<Tag>,
+}
+
+/// Private entry holding the tags whose moderation an agent opted in to. The
+/// latest one on the agent's chain is the current one.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct ModerationOptIn {
+    tags: Vec<Tag>,
+    timestamp: Iso8601,
+}
+
+/// Anchor function from anchors zome.
+fn anchor(anchor_type: String, anchor_text: String) -> ZomeApiResult<Address> {
+    #[derive(Serialize, Deserialize, Debug, DefaultJson)]
+    struct AnchorCallType {
+        anchor: Anchor,
+    }
+    let anchor = Anchor {
+        anchor_type,
+        anchor_text,
+    };
+    let json_string: String = api::call(
+        hdk::THIS_INSTANCE,
+        "anchors",
+        Address::from(api::PUBLIC_TOKEN.to_string()),
+        "anchor",
+        (AnchorCallType { anchor }).into(),
+    )?
+    .into();
+    serde_json::from_str::<ZomeApiResult<Address>>(&json_string)
+        .map_err(|_| ZomeApiError::Internal("Failed to deserialize anchor result".to_owned()))
+        .and_then(|address_result| address_result)
+}
+
+/// Returns `Ok(())` if a flag gives a reason of the right length
+fn validate_flag(flag: &Flag) -> Result<(), String> {
+    if flag.reason.trim().is_empty() {
+        return Err("Flags must give a reason".to_string());
+    }
+    if flag.reason.chars().count() > MAX_REASON_LENGTH {
+        return Err(format!(
+            "Flag reasons must be at most {} characters",
+            MAX_REASON_LENGTH
+        ));
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if every agent in `header`'s provenances is `agent`
+fn validate_provenance(header: &ChainHeader, agent: &Address, error: &str) -> Result<(), String> {
+    if header.provenances().iter().all(|provenance| &provenance.0 == agent) {
+        Ok(())
+    } else {
+        Err(error.to_string())
+    }
+}
+
+/// Returns `Ok(())` if `base` is the anchor of `tag`
+fn validate_tag_anchor(base: &Address, tag: Tag, error: &str) -> Result<(), String> {
+    match utils::get_as_type::<Anchor>(base.clone()) {
+        Ok(ref anchor) if anchor.anchor_type == "tag" && anchor.anchor_text == tag.to_string() => Ok(()),
+        _ => Err(error.to_string()),
+    }
+}
+
+/// Returns `Ok(())` if a link from `base` to `target` is a link from a post
+/// or comment to a flag of it, added or removed (going by `header`) by the
+/// flagger
+fn validate_flag_link(base: &Address, target: &Address, header: &ChainHeader) -> Result<(), String> {
+    match utils::get_as_type::<Flag>(target.clone()) {
+        Ok(flag) => {
+            validate_provenance(header, &flag.key_hash, "Flags must be linked by their flagger")?;
+            if &flag.target == base {
+                Ok(())
+            } else {
+                Err("Flag was not of the entry it is linked from".to_string())
+            }
+        }
+        Err(_) => Err("Link was not flag".to_string()),
+    }
+}
+
+/// Returns `Ok(())` if a link from `base` to `target` is a link from the
+/// anchor of a tag to moderators proposed for that tag, added or removed
+/// (going by `header`) by the agent who proposed them
+fn validate_tag_moderators_link(base: &Address, target: &Address, header: &ChainHeader) -> Result<(), String> {
+    let tag_moderators = utils::get_as_type::<TagModerators>(target.clone())
+        .map_err(|_| "Link was not tag moderators".to_string())?;
+    validate_provenance(
+        header,
+        &tag_moderators.key_hash,
+        "Tag moderators must be linked by the agent who proposed them",
+    )?;
+    validate_tag_anchor(base, tag_moderators.tag, "Tag moderators must be linked from the anchor of their tag")
+}
+
+/// The agent who claimed `tag` first, by the time in the chain header of
+/// their claim. Ties are broken by address.
+///
+/// Claims only count while the post they were made with is in the tag, so a
+/// tag cannot be claimed without posting in it. Chain header times are set
+/// by their authors, so taking over a tag with a backdated claim also takes
+/// a backdated post, which everyone reading the tag can see.
+fn tag_creator(tag: Tag) -> ZomeApiResult<Option<Address>> {
+    let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+    let posts = api::get_links(&tag_anchor, LinkMatch::Exactly("original_tag_to_post"), LinkMatch::Any)?.addresses();
+    let claims = api::get_links(&tag_anchor, LinkMatch::Exactly("tag_creator"), LinkMatch::Any)?.addresses();
+    let mut first: Option<(i64, String, Address)> = None;
+    for address in claims {
+        let claim = match utils::get_as_type::<TagCreator>(address.clone()) {
+            Ok(claim) if claim.tag == tag && posts.contains(&claim.post) => claim,
+            _ => continue,
+        };
+        let time = match created_at(&address)? {
+            Some(time) => time,
+            None => continue,
+        };
+        let key = (time, String::from(address));
+        if first.as_ref().map_or(true, |(first_time, first_address, _)| key < (*first_time, first_address.clone())) {
+            first = Some((key.0, key.1, claim.key_hash));
+        }
+    }
+    Ok(first.map(|(_, _, creator)| creator))
+}
+
+/// Get the creator and current moderators of a tag
+///
+/// Moderators proposed by anyone but the tag's creator are ignored.
+fn handle_tag_moderators(tag: Tag) -> ZomeApiResult<TagModeration> {
+    let creator = tag_creator(tag)?;
+    let mut latest: Option<(i64, Vec<Address>)> = None;
+    if let Some(creator) = &creator {
+        let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+        let proposals = api::get_links_and_load(&tag_anchor, LinkMatch::Exactly("tag_moderators"), LinkMatch::Any)?
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .filter_map(|entry| match entry {
+                Entry::App(_, value) => serde_json::from_str::<TagModerators>(&Into::<String>::into(value)).ok(),
+                _ => None,
+            });
+        for proposal in proposals {
+            if &proposal.key_hash != creator || proposal.tag != tag {
+                continue;
+            }
+            let time = unix_time(&proposal.timestamp).map_err(ZomeApiError::Internal)?;
+            if latest.as_ref().map_or(true, |(latest_time, _)| time > *latest_time) {
+                latest = Some((time, proposal.moderators));
+            }
+        }
+    }
+    Ok(TagModeration {
+        tag,
+        creator,
+        moderators: latest.map(|(_, moderators)| moderators).unwrap_or_default(),
+    })
+}
+
+/// Propose the moderators of a tag. Only the creator of the tag can do this.
+fn handle_set_tag_moderators(tag: Tag, moderators: Vec<Address>, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    if tag_creator(tag)?.as_ref() != Some(&*api::AGENT_ADDRESS) {
+        return Err(ZomeApiError::Internal("Only the creator of a tag can propose its moderators".to_string()));
+    }
+    if moderators.len() > MAX_MODERATORS {
+        return Err(ZomeApiError::Internal(format!(
+            "Tags can have at most {} moderators",
+            MAX_MODERATORS
+        )));
+    }
+    let tag_moderators = TagModerators {
+        tag,
+        moderators,
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp: utc_unix_time.into(),
+    };
+    let address = api::commit_entry(&Entry::App("tag_moderators".into(), tag_moderators.into()))?;
+    let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+    api::link_entries(&tag_anchor, &address, "tag_moderators", "")?;
+    Ok(address)
+}
+
+/// Flag a post or comment for removal from a tag
+fn handle_flag_content(address: Address, tag: Tag, reason: String, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let target = root_address(&address)?;
+    let link_type = match api::get_entry(&target)? {
+        Some(Entry::App(entry_type, _)) => match Into::<String>::into(entry_type).as_ref() {
+            "post" => "post_flag",
+            "comment" => "comment_flag",
+            _ => return Err(ZomeApiError::Internal("Only posts and comments can be flagged".to_string())),
+        },
+        _ => return Err(ZomeApiError::Internal("Only posts and comments can be flagged".to_string())),
+    };
+    let flag = Flag {
+        target: target.clone(),
+        tag,
+        reason,
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp: utc_unix_time.into(),
+    };
+    let address = api::commit_entry(&Entry::App("flag".into(), flag.into()))?;
+    api::link_entries(&target, &address, link_type, "")?;
+    Ok(address)
+}
+
+/// Get all flags of a post or comment
+fn handle_flags(address: Address) -> ZomeApiResult<Vec<Flag>> {
+    Ok(api::get_links_and_load(&root_address(&address)?, LinkMatch::Regex("\\w+?_flag"), LinkMatch::Any)?
+        .into_iter()
+        .filter_map(|result| result.ok())
+        .filter_map(|entry| match entry {
+            Entry::App(_, value) => serde_json::from_str::<Flag>(&Into::<String>::into(value)).ok(),
+            _ => None,
+        })
+        .collect())
+}
+
+/// Get the tags whose moderation this agent opted in to
+fn handle_moderated_tags() -> ZomeApiResult<Vec<Tag>> {
+    Ok(newest_chain_entries::<ModerationOptIn>("moderation_opt_in")?
+        .into_iter()
+        .next()
+        .map(|(_, opt_in)| opt_in.tags)
+        .unwrap_or_default())
+}
+
+/// Commit a new version of the tags whose moderation this agent opted in to
+fn commit_moderated_tags(tags: Vec<Tag>, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let opt_in = ModerationOptIn {
+        tags,
+        timestamp: utc_unix_time.into(),
+    };
+    api::commit_entry(&Entry::App("moderation_opt_in".into(), opt_in.into()))
+}
+
+/// Opt in to the moderation of a tag
+fn handle_opt_in_moderation(tag: Tag, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let mut tags = handle_moderated_tags()?;
+    if !tags.contains(&tag) {
+        tags.push(tag);
+    }
+    commit_moderated_tags(tags, utc_unix_time)
+}
+
+/// Opt out of the moderation of a tag
+fn handle_opt_out_moderation(tag: Tag, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let mut tags = handle_moderated_tags()?;
+    if !tags.contains(&tag) {
+        return Err(ZomeApiError::Internal("Not opted in to moderation of that tag".to_string()));
+    }
+    tags.retain(|moderated_tag| moderated_tag != &tag);
+    commit_moderated_tags(tags, utc_unix_time)
+}
+
+/// Of `items`, get the addresses of the ones that no moderator of a tag this
+/// agent opted in to the moderation of has flagged in that tag.
+///
+/// A flag only counts if its tag is one of the tags the item is shown in, so
+/// a moderator of one tag cannot hide content from the other tags it is in.
+fn handle_unmoderated(items: Vec<ShownIn>) -> ZomeApiResult<Vec<Address>> {
+    let mut moderators: HashMap<Tag, Vec<Address>> = HashMap::new();
+    for tag in handle_moderated_tags()? {
+        moderators.insert(tag, handle_tag_moderators(tag)?.moderators);
+    }
+    let mut unmoderated = Vec::new();
+    for item in items {
+        let moderated = item.tags.iter().any(|tag| moderators.contains_key(tag));
+        let removed = moderated
+            && handle_flags(item.address.clone())?.iter().any(|flag| {
+                item.tags.contains(&flag.tag)
+                    && moderators
+                        .get(&flag.tag)
+                        .map_or(false, |tag_moderators| tag_moderators.contains(&flag.key_hash))
+            });
+        if !removed {
+            unmoderated.push(item.address);
+        }
+    }
+    Ok(unmoderated)
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "tag_moderators",
+            description: "Moderators proposed for a tag",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<TagModerators>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: tag_moderators,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| provenance.0 == tag_moderators.key_hash) {
+                            return Err("Cannot propose moderators for another agent.".to_string());
+                        }
+                        if tag_moderators.moderators.len() > MAX_MODERATORS {
+                            return Err(format!("Tags can have at most {} moderators", MAX_MODERATORS));
+                        }
+                        validate_timestamp(&tag_moderators.timestamp, &validation_data.package.chain_header)
+                    }
+                    _ => Err("Tag moderators cannot be changed, propose them again".to_string()),
+                }
+            },
+            links: [
+                from!(
+                    "anchor",
+                    link_type: "tag_moderators",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        validate_tag_moderators_link(
+                            link.link().base(),
+                            link.link().target(),
+                            &validation_data.package.chain_header,
+                        )
+                    }
+                )
+            ]
+        ),
+        entry!(
+            name: "flag",
+            description: "Request to remove a post or comment from a tag",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<Flag>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: flag,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| provenance.0 == flag.key_hash) {
+                            return Err("Cannot flag content for another agent.".to_string());
+                        }
+                        validate_flag(&flag)?;
+                        validate_timestamp(&flag.timestamp, &validation_data.package.chain_header)
+                    }
+                    _ => Err("Flags cannot be changed".to_string()),
+                }
+            },
+            links: [
+                from!(
+                    "post",
+                    link_type: "post_flag",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        validate_flag_link(
+                            link.link().base(),
+                            link.link().target(),
+                            &validation_data.package.chain_header,
+                        )
+                    }
+                ),
+                from!(
+                    "comment",
+                    link_type: "comment_flag",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        validate_flag_link(
+                            link.link().base(),
+                            link.link().target(),
+                            &validation_data.package.chain_header,
+                        )
+                    }
+                )
+            ]
+        ),
+        entry!(
+            name: "moderation_opt_in",
+            description: "Private list of the tags whose moderation an agent opted in to",
+            sharing: Sharing::Private,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<ModerationOptIn>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: opt_in,
+                        validation_data,
+                    } => validate_timestamp(&opt_in.timestamp, &validation_data.package.chain_header),
+                    _ => Err("Moderation opt ins cannot be changed, opt in or out again".to_string()),
+                }
+            }
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        tag_moderators: {
+            inputs: |tag: Tag|,
+            outputs: |result: ZomeApiResult<TagModeration>|,
+            handler: handle_tag_moderators
+        }
+        set_tag_moderators: {
+            inputs: |tag: Tag, moderators: Vec<Address>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_set_tag_moderators
+        }
+        flag_content: {
+            inputs: |address: Address, tag: Tag, reason: String, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_flag_content
+        }
+        flags: {
+            inputs: |address: Address|,
+            outputs: |result: ZomeApiResult<Vec<Flag>>|,
+            handler: handle_flags
+        }
+        opt_in_moderation: {
+            inputs: |tag: Tag, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_opt_in_moderation
+        }
+        opt_out_moderation: {
+            inputs: |tag: Tag, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_opt_out_moderation
+        }
+        moderated_tags: {
+            inputs: | |,
+            outputs: |result: ZomeApiResult<Vec<Tag>>|,
+            handler: handle_moderated_tags
+        }
+        unmoderated: {
+            inputs: |items: Vec<ShownIn>|,
+            outputs: |result: ZomeApiResult<Vec<Address>>|,
+            handler: handle_unmoderated
+        }
+    ]
+
+    traits: {
+        hc_public [
+            tag_moderators,
+            set_tag_moderators,
+            flag_content,
+            flags,
+            opt_in_moderation,
+            opt_out_moderation,
+            moderated_tags,
+            unmoderated
+        ]
+    }
+}
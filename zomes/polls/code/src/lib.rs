@@ -0,0 +1,558 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate comet_common;
+
+use hdk::api;
+use hdk::{EntryValidationData, LinkValidationData};
+use hdk::error::{ZomeApiError, ZomeApiResult};
+use hdk::holochain_core_types::{
+    dna::entry_types::Sharing, entry::Entry, chain_header::ChainHeader,
+    time::Iso8601, link::LinkMatch
+};
+use hdk::holochain_persistence_api::cas::content::Address;
+use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
+use hdk::utils;
+use hdk::ValidationPackageDefinition;
+use holochain_wasm_utils::api_serialization::get_entry::{GetEntryResultType, StatusRequestKind};
+use comet_common::{created_at, live_chain_entries, options_with_headers, root_address, unix_time, validate_timestamp};
+use std::collections::{HashMap, HashSet};
+
+/// Most options a poll can have
+const MAX_POLL_OPTIONS: usize = 32;
+
+/// Longest a poll option can be, in bytes
+const MAX_OPTION_LENGTH: usize = 256;
+
+/// Weight of a vote from an agent with default trust, in millionths
+///
+/// Should be exact same as in `votes` zome
+const FULL_WEIGHT: i64 = 1_000_000;
+
+/// A poll attached to a post of the `poll` kind
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct Poll {
+    /// Address of the first version of the post this poll is attached to
+    post: Address,
+    options: Vec<String>,
+    /// Whether voters can choose more than one option
+    multiple_choice: bool,
+    /// Time voting opens
+    opens_at: Iso8601,
+    /// Time voting closes, if it ever does
+    closes_at: Option<Iso8601>,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// An agent's choices in a poll. Every agent can vote in a poll once.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct PollVote {
+    poll: Address,
+    /// Indices of the chosen options
+    choices: Vec<u32>,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// Trust result from votes zome.
+///
+/// Should be exact same as in `votes` zome
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct Trust {
+    agent: Address,
+    weight: i64,
+}
+
+/// Results for a single option of a poll
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct OptionResult {
+    option: String,
+    /// How many agents chose this option
+    votes: u32,
+    /// Total weight of the agents that chose this option, in millionths
+    weight: i64,
+}
+
+/// Results of a poll
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct PollResults {
+    poll: Address,
+    options: Vec<OptionResult>,
+    /// How many agents voted
+    voters: u32,
+}
+
+/// Get the score weights the calling agent gives `agents` from the votes zome
+fn trust(agents: Vec<Address>) -> ZomeApiResult<Vec<Trust>> {
+    #[derive(Serialize, Deserialize, Debug, DefaultJson)]
+    struct TrustCallType {
+        agents: Vec<Address>,
+    }
+    let json_string: String = api::call(
+        hdk::THIS_INSTANCE,
+        "votes",
+        Address::from(api::PUBLIC_TOKEN.to_string()),
+        "trust",
+        (TrustCallType { agents }).into(),
+    )?
+    .into();
+    serde_json::from_str::<ZomeApiResult<Vec<Trust>>>(&json_string)
+        .map_err(|_| ZomeApiError::Internal("Failed to deserialize trust result".to_owned()))
+        .and_then(|trust_result| trust_result)
+}
+
+/// Returns `Ok(())` if the post at `post` is a poll post written by `author`
+fn validate_poll_post(post: &Address, author: &Address) -> Result<(), String> {
+    let value = match api::get_entry(post) {
+        Ok(Some(Entry::App(entry_type, value))) => {
+            if String::from(entry_type) != "post" {
+                return Err("Polls must be attached to posts".to_string());
+            }
+            serde_json::from_str::<serde_json::Value>(&Into::<String>::into(value))
+                .map_err(|_| "Failed to parse poll post".to_string())?
+        }
+        _ => return Err("Polls must be attached to posts".to_string()),
+    };
+    if value["kind"]["type"] != "poll" {
+        Err("Polls can only be attached to poll posts".to_string())
+    } else if value["key_hash"].as_str() != Some(String::from(author.clone()).as_str()) {
+        Err("Cannot attach poll to post that is not yours.".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if a poll has a sensible set of options and voting times
+fn validate_poll(poll: &Poll) -> Result<(), String> {
+    if poll.options.len() < 2 || poll.options.len() > MAX_POLL_OPTIONS {
+        return Err(format!(
+            "Polls must have between 2 and {} options",
+            MAX_POLL_OPTIONS
+        ));
+    }
+    if poll.options.iter().any(|option| option.is_empty() || option.len() > MAX_OPTION_LENGTH) {
+        return Err(format!(
+            "Poll options must be between 1 and {} bytes",
+            MAX_OPTION_LENGTH
+        ));
+    }
+    if let Some(closes_at) = &poll.closes_at {
+        if unix_time(closes_at)? <= unix_time(&poll.opens_at)? {
+            return Err("Polls must close after they open".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if `poll_vote` makes a valid choice in its poll and was
+/// cast while the poll was open, going by its chain header
+fn validate_poll_vote(poll_vote: &PollVote, header: &ChainHeader) -> Result<(), String> {
+    let poll: Poll = utils::get_as_type(poll_vote.poll.clone())
+        .map_err(|_| "Poll votes must be on a poll".to_string())?;
+
+    if poll_vote.choices.is_empty() {
+        return Err("Poll votes must choose an option".to_string());
+    }
+    if !poll.multiple_choice && poll_vote.choices.len() > 1 {
+        return Err("Only one option can be chosen in this poll".to_string());
+    }
+    let mut seen = HashSet::new();
+    for choice in &poll_vote.choices {
+        if *choice as usize >= poll.options.len() {
+            return Err("Poll vote chooses an option that does not exist".to_string());
+        }
+        if !seen.insert(choice) {
+            return Err("Poll vote chooses the same option twice".to_string());
+        }
+    }
+
+    let cast_at = unix_time(header.timestamp())?;
+    if cast_at < unix_time(&poll.opens_at)? {
+        return Err("Poll is not open yet".to_string());
+    }
+    if let Some(closes_at) = &poll.closes_at {
+        if cast_at >= unix_time(closes_at)? {
+            return Err("Poll is closed".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if a poll is linked from the post it is attached to by
+/// its author. When `source_chain_entries` is given (for added links), the
+/// author must also have no other live poll on the post.
+///
+/// Only the author of a post can attach polls to it, so looking in their
+/// chain from the validation package finds every other poll on the post.
+fn validate_poll_link(
+    post: &Address,
+    poll_address: &Address,
+    header: &ChainHeader,
+    source_chain_entries: Option<&Option<Vec<Entry>>>,
+) -> Result<(), String> {
+    let poll: Poll = utils::get_as_type(poll_address.clone())
+        .map_err(|_| "Failed to get poll for link validation".to_string())?;
+    if &poll.post != post {
+        return Err("Poll must be linked from its post".to_string());
+    }
+    if !header.provenances().iter().all(|provenance| provenance.0 == poll.key_hash) {
+        return Err("Polls must be linked by their author".to_string());
+    }
+    if let Some(source_chain_entries) = source_chain_entries {
+        let has_poll = live_chain_entries::<Poll>(source_chain_entries, "poll")?
+            .into_iter()
+            .any(|(address, other_poll)| &address != poll_address && &other_poll.post == post);
+        if has_poll {
+            return Err("Post already has a poll".to_string());
+        }
+    }
+    Ok(())
+}
+
+/// Returns `Ok(())` if a poll vote is linked from its poll by its author, and
+/// its author has not voted in that poll before.
+///
+/// Earlier votes are looked for in `source_chain_entries`, the author's chain
+/// from the validation package, so every validator checks the same chain.
+fn validate_poll_vote_link(
+    poll: &Address,
+    poll_vote_address: &Address,
+    header: &ChainHeader,
+    source_chain_entries: &Option<Vec<Entry>>,
+) -> Result<(), String> {
+    let poll_vote: PollVote = utils::get_as_type(poll_vote_address.clone())
+        .map_err(|_| "Failed to get poll vote for link validation".to_string())?;
+    if &poll_vote.poll != poll {
+        return Err("Poll vote must be linked from its poll".to_string());
+    }
+    if !header.provenances().iter().all(|provenance| provenance.0 == poll_vote.key_hash) {
+        return Err("Poll votes must be linked by their voter".to_string());
+    }
+
+    let already_voted = live_chain_entries::<PollVote>(source_chain_entries, "poll_vote")?
+        .into_iter()
+        .filter(|(address, _)| address != poll_vote_address)
+        .any(|(_, other_vote)| &other_vote.poll == poll && other_vote.key_hash == poll_vote.key_hash);
+
+    if already_voted {
+        Err("You've already voted in this poll".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the address of the poll attached to `post`
+///
+/// Should more than one get through, the first one counts, going by the time
+/// of its chain header (then by address), so every agent finds the same poll.
+fn find_poll(post: &Address) -> ZomeApiResult<Address> {
+    let mut first: Option<(i64, String)> = None;
+    for address in api::get_links(post, LinkMatch::Exactly("post_poll"), LinkMatch::Any)?.addresses() {
+        let time = match created_at(&address)? {
+            Some(time) => time,
+            None => continue,
+        };
+        let key = (time, String::from(address));
+        if first.as_ref().map_or(true, |first| &key < first) {
+            first = Some(key);
+        }
+    }
+    first
+        .map(|(_, address)| Address::from(address))
+        .ok_or_else(|| ZomeApiError::Internal("Post has no poll".to_string()))
+}
+
+/// Attach a poll to a post of the `poll` kind
+///
+/// Voting opens at `opens_at` (or `utc_unix_time` if not given) and closes at
+/// `closes_at`, if given. `post` can be the address of any version of the
+/// post.
+fn handle_create_poll(
+    post: Address,
+    options: Vec<String>,
+    multiple_choice: bool,
+    opens_at: Option<u64>,
+    closes_at: Option<u64>,
+    utc_unix_time: u64,
+) -> ZomeApiResult<Address> {
+    let post = root_address(&post)?;
+    if find_poll(&post).is_ok() {
+        return Err(ZomeApiError::Internal("Post already has a poll".to_string()));
+    }
+    let poll = Poll {
+        post: post.clone(),
+        options,
+        multiple_choice,
+        opens_at: opens_at.unwrap_or(utc_unix_time).into(),
+        closes_at: closes_at.map(Into::into),
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp: utc_unix_time.into(),
+    };
+    let address = api::commit_entry(&Entry::App("poll".into(), poll.into()))?;
+    api::link_entries(&post, &address, "post_poll", "")?;
+    Ok(address)
+}
+
+/// Get the poll attached to a post
+fn handle_get_poll(post: Address) -> ZomeApiResult<Poll> {
+    utils::get_as_type(find_poll(&root_address(&post)?)?)
+}
+
+/// Vote in the poll attached to a post, choosing the options at the indices
+/// in `choices`
+fn handle_poll_vote(post: Address, choices: Vec<u32>, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let poll = find_poll(&root_address(&post)?)?;
+    let poll_vote = PollVote {
+        poll: poll.clone(),
+        choices,
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp: utc_unix_time.into(),
+    };
+    let address = api::commit_entry(&Entry::App("poll_vote".into(), poll_vote.into()))?;
+    api::link_entries(&poll, &address, "poll_vote", "")?;
+    Ok(address)
+}
+
+/// Count the votes in the poll attached to a post
+///
+/// Only the first vote of each agent counts, going by the time of its chain
+/// header (then by address), in case more than one got through.
+///
+/// When `weighted`, each agent's choices count for as much as the calling
+/// agent trusts them, the same way votes on posts and comments are weighted.
+/// Otherwise every agent counts for `FULL_WEIGHT`.
+fn handle_poll_results(post: Address, weighted: bool) -> ZomeApiResult<PollResults> {
+    let poll_address = find_poll(&root_address(&post)?)?;
+    let poll: Poll = utils::get_as_type(poll_address.clone())?;
+
+    let mut first_votes: HashMap<Address, (i64, String, PollVote)> = HashMap::new();
+    for address in api::get_links(&poll_address, LinkMatch::Exactly("poll_vote"), LinkMatch::Any)?.addresses() {
+        let item = match api::get_entry_result(&address, options_with_headers(StatusRequestKind::Initial))?.result {
+            GetEntryResultType::Single(item) => item,
+            GetEntryResultType::All(_) => continue,
+        };
+        let (poll_vote, header) = match (item.entry, item.headers.first()) {
+            (Some(Entry::App(_, value)), Some(header)) => {
+                match serde_json::from_str::<PollVote>(&Into::<String>::into(value)) {
+                    Ok(poll_vote) => (poll_vote, header.clone()),
+                    Err(_) => continue,
+                }
+            }
+            _ => continue,
+        };
+        if poll_vote.poll != poll_address {
+            continue;
+        }
+        let cast_at = unix_time(header.timestamp()).map_err(ZomeApiError::Internal)?;
+        let address = String::from(address);
+        let earlier = match first_votes.get(&poll_vote.key_hash) {
+            Some((first_cast_at, first_address, _)) => (cast_at, &address) < (*first_cast_at, first_address),
+            None => true,
+        };
+        if earlier {
+            first_votes.insert(poll_vote.key_hash.clone(), (cast_at, address, poll_vote));
+        }
+    }
+    let mut poll_votes: Vec<(i64, String, PollVote)> = first_votes.into_iter().map(|(_, first)| first).collect();
+    poll_votes.sort_by(|(a_time, a_address, _), (b_time, b_address, _)| (a_time, a_address).cmp(&(b_time, b_address)));
+    let poll_votes: Vec<PollVote> = poll_votes.into_iter().map(|(_, _, poll_vote)| poll_vote).collect();
+
+    let weights: Vec<i64> = if weighted {
+        trust(poll_votes.iter().map(|poll_vote| poll_vote.key_hash.clone()).collect())?
+            .into_iter()
+            .map(|trust| trust.weight)
+            .collect()
+    } else {
+        vec![FULL_WEIGHT; poll_votes.len()]
+    };
+
+    let mut options: Vec<OptionResult> = poll
+        .options
+        .into_iter()
+        .map(|option| OptionResult {
+            option,
+            votes: 0,
+            weight: 0,
+        })
+        .collect();
+    for (poll_vote, weight) in poll_votes.iter().zip(weights) {
+        for choice in &poll_vote.choices {
+            if let Some(result) = options.get_mut(*choice as usize) {
+                result.votes += 1;
+                result.weight += weight;
+            }
+        }
+    }
+
+    Ok(PollResults {
+        poll: poll_address,
+        options,
+        voters: poll_votes.len() as u32,
+    })
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "poll",
+            description: "Options and voting times of a poll post",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<Poll>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: poll,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if provenances.iter().all(|provenance| provenance.0 == poll.key_hash) {
+                            validate_poll(&poll)?;
+                            validate_poll_post(&poll.post, &poll.key_hash)?;
+                            validate_timestamp(&poll.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            Err("Cannot create poll for somebody else.".to_string())
+                        }
+                    }
+                    EntryValidationData::Modify { .. } => {
+                        Err("Polls cannot be changed".to_string())
+                    }
+                    EntryValidationData::Delete {
+                        old_entry: old_poll,
+                        old_entry_header,
+                        validation_data,
+                    } => {
+                        let mut provenances = validation_data.package.chain_header.provenances()
+                            .iter()
+                            .chain(old_entry_header.provenances());
+                        if provenances.all(|provenance| provenance.0 == old_poll.key_hash) {
+                            Ok(())
+                        } else {
+                            Err("Cannot delete poll that is not yours.".to_string())
+                        }
+                    }
+                }
+            },
+            links: [
+                from!(
+                    "post",
+                    link_type: "post_poll",
+                    validation_package: || ValidationPackageDefinition::ChainEntries,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => validate_poll_link(
+                                link.link().base(),
+                                link.link().target(),
+                                &validation_data.package.chain_header,
+                                Some(&validation_data.package.source_chain_entries),
+                            ),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => validate_poll_link(
+                                link.link().base(),
+                                link.link().target(),
+                                &validation_data.package.chain_header,
+                                None,
+                            ),
+                        }
+                    }
+                )
+            ]
+        ),
+        entry!(
+            name: "poll_vote",
+            description: "An agent's choices in a poll",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<PollVote>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: poll_vote,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if provenances.iter().all(|provenance| provenance.0 == poll_vote.key_hash) {
+                            validate_poll_vote(&poll_vote, &validation_data.package.chain_header)?;
+                            validate_timestamp(&poll_vote.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            Err("Cannot vote in poll for somebody else.".to_string())
+                        }
+                    }
+                    _ => Err("Poll votes cannot be changed".to_string()),
+                }
+            },
+            links: [
+                from!(
+                    "poll",
+                    link_type: "poll_vote",
+                    validation_package: || ValidationPackageDefinition::ChainEntries,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => validate_poll_vote_link(
+                                link.link().base(),
+                                link.link().target(),
+                                &validation_data.package.chain_header,
+                                &validation_data.package.source_chain_entries,
+                            ),
+                            LinkValidationData::LinkRemove { .. } => {
+                                Err("Poll votes cannot be removed".to_string())
+                            }
+                        }
+                    }
+                )
+            ]
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        create_poll: {
+            inputs: |post: Address, options: Vec<String>, multiple_choice: bool, opens_at: Option<u64>, closes_at: Option<u64>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_create_poll
+        }
+        get_poll: {
+            inputs: |post: Address|,
+            outputs: |result: ZomeApiResult<Poll>|,
+            handler: handle_get_poll
+        }
+        poll_vote: {
+            inputs: |post: Address, choices: Vec<u32>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_poll_vote
+        }
+        poll_results: {
+            inputs: |post: Address, weighted: bool|,
+            outputs: |result: ZomeApiResult<PollResults>|,
+            handler: handle_poll_results
+        }
+    ]
+
+    traits: {
+        hc_public [
+            create_poll,
+            get_poll,
+            poll_vote,
+            poll_results
+        ]
+    }
+}
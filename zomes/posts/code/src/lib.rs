@@ -6,6 +6,7 @@ extern crate serde_derive;
 extern crate serde_json;
 #[macro_use]
 extern crate holochain_json_derive;
+extern crate comet_common;
 // extern crate anchors;
 
 // use anchors::Anchor;
@@ -23,13 +24,18 @@ use hdk::{
     error::{ZomeApiError, ZomeApiResult},
     holochain_core_types::{
         dna::entry_types::Sharing, entry::Entry,
-        time::Iso8601, link::LinkMatch,
+        time::Iso8601, link::{Link, LinkMatch},
         chain_header::ChainHeader
     },
-    holochain_persistence_api::cas::content::Address,
+    holochain_persistence_api::cas::content::{Address, AddressableContent},
     holochain_wasm_utils::api_serialization::get_links::GetLinksResult,
     ValidationPackageDefinition,
 };
+use comet_common::{
+    author_link_tag, authored_page, blocked, get_history_with_headers, get_latest_with_header, initial_header,
+    root_address, unix_time, validate_attachments, validate_image_manifest, validate_timestamp, validate_url,
+    Blockable, CometProperties, CrosspostPolicy,
+};
 use std::{
     collections::{HashMap as HMap, HashSet},
     iter::FromIterator,
@@ -50,8 +56,136 @@ struct Post {
     /// Time of the post creation.
     /// They are used to avoid accidental hash collisions.
     ///
-    /// *Should not be used as real timestamp.*
+    /// Validated to be within `TIMESTAMP_TOLERANCE` of the chain header, but
+    /// `TimedPost.header_timestamp` should be preferred.
     timestamp: Iso8601,
+    /// Whether this post has been replaced by a tombstone. The title and
+    /// content of deleted posts are `DELETED_TEXT`.
+    ///
+    /// Left out when `false`, so posts from before tombstones still
+    /// deserialize and keep their addresses.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    deleted: bool,
+    /// What kind of post this is.
+    ///
+    /// Left out for text posts, so posts from before post kinds still
+    /// deserialize and keep their addresses.
+    #[serde(default, skip_serializing_if = "PostKind::is_text")]
+    kind: PostKind,
+    /// Addresses of the manifests of files (from the `files` zome) attached
+    /// to this post
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<Address>,
+}
+
+/// Text that replaces the title and content of deleted posts
+const DELETED_TEXT: &str = "[deleted]";
+
+/// How many items are returned per page of an agent's activity
+const PAGE_SIZE: usize = 25;
+
+/// The kinds of posts. Every kind has a title and `content`, which for kinds
+/// other than `Text` is an optional description.
+#[derive(Debug, Clone, DefaultJson, Serialize, Deserialize, PartialEq)]
+#[serde(rename_all = "lowercase")]
+#[serde(tag = "type")]
+enum PostKind {
+    /// Markdown text
+    Text,
+    /// A link to a web page
+    Link {
+        url: String,
+        preview: Option<LinkPreview>,
+    },
+    /// An image, either hosted elsewhere at `url` or uploaded with the
+    /// `files` zome, in which case `manifest` is the address of its manifest.
+    /// Exactly one of the two is set.
+    ///
+    /// `manifest` is left out when it is not set, so image posts from before
+    /// it still deserialize and keep their addresses.
+    Image {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        url: Option<String>,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        manifest: Option<Address>,
+        alt_text: Option<String>,
+    },
+    /// Any other file hosted elsewhere
+    File {
+        url: String,
+        name: String,
+    },
+    /// A question to vote on. The options and voting times are stored in a
+    /// `poll` entry (from the `polls` zome) linked from the post.
+    Poll,
+}
+
+impl Default for PostKind {
+    fn default() -> Self {
+        PostKind::Text
+    }
+}
+
+impl PostKind {
+    fn is_text(&self) -> bool {
+        *self == PostKind::Text
+    }
+
+    /// The `type` this kind is serialized with
+    fn name(&self) -> &'static str {
+        match self {
+            PostKind::Text => "text",
+            PostKind::Link { .. } => "link",
+            PostKind::Image { .. } => "image",
+            PostKind::File { .. } => "file",
+            PostKind::Poll => "poll",
+        }
+    }
+}
+
+/// Metadata shown alongside a link before it is followed
+#[derive(Debug, Clone, DefaultJson, Serialize, Deserialize, PartialEq)]
+struct LinkPreview {
+    title: Option<String>,
+    description: Option<String>,
+    image_url: Option<String>,
+}
+
+/// Longest text allowed in link previews, image alt text and file names, in
+/// bytes
+const MAX_METADATA_LENGTH: usize = 1024;
+
+/// A post along with the time it was committed and who committed it, taken
+/// from its chain header
+#[derive(Debug, Clone, DefaultJson, Serialize, Deserialize)]
+struct TimedPost {
+    post: Post,
+    /// Address of this version of the post
+    address: Address,
+    header_timestamp: Iso8601,
+    author: Address,
+    /// Whether this version replaced an earlier one
+    edited: bool,
+}
+
+/// The latest version of a post, along with whether it has been edited
+#[derive(Debug, Clone, DefaultJson, Serialize, Deserialize)]
+struct LatestPost {
+    #[serde(flatten)]
+    post: Post,
+    /// Whether this version replaced an earlier one
+    edited: bool,
+}
+
+/// A post or comment made by an agent
+#[derive(Debug, Clone, DefaultJson, Serialize, Deserialize, PartialEq)]
+struct Activity {
+    /// Address of the first version of the post or comment
+    address: Address,
+    /// Either `post` or `comment`
+    entry_type: String,
+    /// Time the first version was committed, taken from its chain header
+    header_timestamp: Iso8601,
 }
 
 /// The type of post that the "client" will give the "server".
@@ -61,6 +195,11 @@ struct PostContent {
     title: String,
     content: String,
     utc_unix_time: u64,
+    /// Text posts can leave this out
+    #[serde(default)]
+    kind: PostKind,
+    #[serde(default)]
+    attachments: Vec<Address>,
 }
 
 impl Into<Post> for PostContent {
@@ -70,6 +209,9 @@ impl Into<Post> for PostContent {
             content: self.content,
             key_hash: api::AGENT_ADDRESS.clone(),
             timestamp: self.utc_unix_time.into(),
+            deleted: false,
+            kind: self.kind,
+            attachments: self.attachments,
         }
     }
 }
@@ -80,6 +222,19 @@ impl Into<Post> for PostContent {
 /// Must implement `ToString` for anchors.
 type Tag = u64;
 
+/// Claim to be the creator of a tag, linked from the tag's anchor and made
+/// with the first post in the tag. Read by the `moderation` zome.
+///
+/// Should be exact same as in `moderation` zome
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct TagCreator {
+    tag: Tag,
+    /// The post the tag was claimed with
+    post: Address,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
 /// Represents post search queries.
 ///
 /// # Examples
@@ -131,6 +286,52 @@ struct PostTags {
     crosspost_tags: Vec<Tag>,
 }
 
+/// A link from `base` to `target` of type `link_type`
+#[derive(Deserialize, Serialize, DefaultJson, Debug, Clone, PartialEq)]
+struct PostLink {
+    base: Address,
+    target: Address,
+    link_type: String,
+    /// The tag of the link. Empty for every link but crossposts, which are
+    /// tagged with the address of the agent who made them.
+    tag: String,
+}
+
+/// A crosspost of a post to a tag
+#[derive(Deserialize, Serialize, DefaultJson, Debug, Clone, PartialEq)]
+struct Crosspost {
+    tag: Tag,
+    /// Agent who made the crosspost. `None` for crossposts made before
+    /// crossposters were recorded.
+    crossposter: Option<Address>,
+}
+
+/// A link that could not be added or removed, and why
+#[derive(Deserialize, Serialize, DefaultJson, Debug, Clone)]
+struct LinkFailure {
+    link: PostLink,
+    error: String,
+}
+
+/// The result of deleting a post
+#[derive(Deserialize, Serialize, DefaultJson, Debug)]
+struct DeleteReport {
+    /// Address of the deletion of the post entry.
+    ///
+    /// `None` if any link could not be removed, in which case the post entry
+    /// is left in place so that deleting it can be retried.
+    deletion_address: Option<Address>,
+    failed_links: Vec<LinkFailure>,
+}
+
+/// The result of repairing the links of a post
+#[derive(Deserialize, Serialize, DefaultJson, Debug)]
+struct RepairReport {
+    added_links: Vec<PostLink>,
+    removed_links: Vec<PostLink>,
+    failed_links: Vec<LinkFailure>,
+}
+
 /// Flattens an `Iterator` of `HashMap<Address, InTermsOf>` to a
 /// `HashMap<Address, InTermsOf>`, combining `InTermsOf`s.
 ///
@@ -197,7 +398,16 @@ fn anchor(anchor_type: String, anchor_text: String) -> ZomeApiResult<Address> {
 }
 
 /// Turn a search query into a JsonString containing the results
-fn handle_search(query: Search, exclude_crossposts: bool) -> ZomeApiResult<Vec<SearchResult>> {
+///
+/// When `apply_filters` is set, posts blocked by the calling agent are left
+/// out. When `respect_moderation` is set, so are posts removed by the
+/// moderators of tags whose moderation the calling agent opted in to.
+fn handle_search(
+    query: Search,
+    exclude_crossposts: bool,
+    apply_filters: bool,
+    respect_moderation: bool,
+) -> ZomeApiResult<Vec<SearchResult>> {
     fn handle_search_helper(
         query: Search,
         exclude_crossposts: bool,
@@ -286,11 +496,91 @@ fn handle_search(query: Search, exclude_crossposts: bool) -> ZomeApiResult<Vec<S
             }
         }
     }
-    handle_search_helper(query, exclude_crossposts).map(|hmap| {
-        hmap.into_iter()
-            .map(|result| result.into())
-            .collect::<Vec<SearchResult>>()
-    })
+    let mut results = handle_search_helper(query, exclude_crossposts)?;
+    if respect_moderation {
+        let kept = unmoderated(&results)?;
+        results.retain(|address, _| kept.contains(address));
+    }
+    if apply_filters {
+        let kept = unblocked_posts(results.keys().cloned().collect())?;
+        results.retain(|address, _| kept.contains(address));
+    }
+    Ok(results.into_iter().map(SearchResult::from).collect())
+}
+
+/// Of the posts in `results`, get the addresses of the ones not removed by
+/// moderation the calling agent opted in to, from the moderation zome. Only
+/// flags in the tags a post was found in count.
+fn unmoderated(results: &HMap<Address, InTermsOf>) -> ZomeApiResult<Vec<Address>> {
+    #[derive(Serialize, Deserialize, Debug)]
+    struct ShownIn {
+        address: Address,
+        tags: Vec<u64>,
+    }
+    #[derive(Serialize, Deserialize, Debug, DefaultJson)]
+    struct UnmoderatedCallType {
+        items: Vec<ShownIn>,
+    }
+    let items = results
+        .iter()
+        .map(|(address, in_terms_of)| ShownIn {
+            address: address.clone(),
+            tags: in_terms_of.iter().cloned().collect(),
+        })
+        .collect();
+    let json_string: String = api::call(
+        hdk::THIS_INSTANCE,
+        "moderation",
+        Address::from(api::PUBLIC_TOKEN.to_string()),
+        "unmoderated",
+        (UnmoderatedCallType { items }).into(),
+    )?
+    .into();
+    serde_json::from_str::<ZomeApiResult<Vec<Address>>>(&json_string)
+        .map_err(|_| ZomeApiError::Internal("Failed to deserialize moderation result".to_owned()))
+        .and_then(|addresses_result| addresses_result)
+}
+
+/// Claim to be the creator of `tag` with the post at `post_address`, if
+/// nobody has claimed it yet. The moderation zome decides which claim counts.
+fn claim_tag(post_address: &Address, tag: Tag, timestamp: Iso8601) -> ZomeApiResult<()> {
+    let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+    if !api::get_links(&tag_anchor, LinkMatch::Exactly("tag_creator"), LinkMatch::Any)?.addresses().is_empty() {
+        return Ok(());
+    }
+    let claim = TagCreator {
+        tag,
+        post: post_address.clone(),
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp,
+    };
+    let address = api::commit_entry(&Entry::App("tag_creator".into(), claim.into()))?;
+    api::link_entries(&tag_anchor, &address, "tag_creator", "")?;
+    Ok(())
+}
+
+/// Of `addresses`, get the ones of posts the calling agent has not blocked,
+/// because of their author, their original tags or a keyword in their title
+/// or content. Addresses that are not posts are kept.
+fn unblocked_posts(addresses: Vec<Address>) -> ZomeApiResult<Vec<Address>> {
+    let mut posts: Vec<(Address, Option<Blockable>)> = Vec::new();
+    for address in addresses {
+        let item = match utils::get_as_type::<Post>(address.clone()) {
+            Ok(post) => Some(Blockable {
+                author: post.key_hash,
+                tags: handle_post_tags(address.clone())?.original_tags,
+                texts: vec![post.title, post.content],
+            }),
+            Err(_) => None,
+        };
+        posts.push((address, item));
+    }
+    let mut is_blocked = blocked(posts.iter().filter_map(|(_, item)| item.clone()).collect())?.into_iter();
+    Ok(posts
+        .into_iter()
+        .filter(|(_, item)| item.is_none() || !is_blocked.next().unwrap_or(false))
+        .map(|(address, _)| address)
+        .collect())
 }
 
 /// Create a post and link to to/from a set of tags
@@ -301,10 +591,16 @@ fn handle_create_post(post: PostContent, tags: Vec<Tag>) -> ZomeApiResult<Addres
 /// Create a post given a full `Post` struct, including `timestamp` and
 /// `key_hash`
 fn handle_create_post_raw(post: Post, tags: Vec<Tag>) -> ZomeApiResult<Address> {
+    let max_tags = CometProperties::load().map_err(ZomeApiError::Internal)?.max_tags_per_post;
+    if tags.len() > max_tags {
+        return Err(ZomeApiError::Internal(format!("Posts can have at most {} tags", max_tags)));
+    }
+    let timestamp = post.timestamp.clone();
+    let author_tag = author_link_tag(&post.timestamp).map_err(ZomeApiError::Internal)?;
     let post_entry = Entry::App("post".into(), post.into());
     let post_entry_address = api::commit_entry(&post_entry)?;
     // Link from author
-    api::link_entries(&api::AGENT_ADDRESS, &post_entry_address, "post_author", "")?;
+    api::link_entries(&api::AGENT_ADDRESS, &post_entry_address, "post_author", &author_tag)?;
     for tag in tags {
         let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
         // Link from post to tag anchor and vice versa
@@ -316,90 +612,772 @@ fn handle_create_post_raw(post: Post, tags: Vec<Tag>) -> ZomeApiResult<Address>
             "",
             "",
         )?;
+        // The first agent to post in a tag becomes its creator. Creators are
+        // only used for moderation, so the post is kept even if this fails.
+        claim_tag(&post_entry_address, tag, timestamp.clone()).ok();
     }
     Ok(post_entry_address)
 }
 
-/// Read a post into a JsonString
-fn handle_read_post(address: Address) -> ZomeApiResult<Post> {
-    utils::get_as_type(address)
+/// Returns `Ok(())` if a claim of a tag is made with a post by the claimant
+fn validate_tag_creator(claim: &TagCreator) -> Result<(), String> {
+    match get_original_post(&claim.post) {
+        Ok(ref post) if post.key_hash == claim.key_hash => Ok(()),
+        _ => Err("Tags can only be claimed with a post by the claimant".to_string()),
+    }
+}
+
+/// Read the latest version of a post, with whether it has been edited
+fn handle_read_post(address: Address) -> ZomeApiResult<LatestPost> {
+    let timed_post = handle_read_timed_post(address)?;
+    Ok(LatestPost {
+        post: timed_post.post,
+        edited: timed_post.edited,
+    })
+}
+
+/// Build a `TimedPost` from a post entry and its chain header
+fn to_timed_post(address: Address, entry: Entry, header: ChainHeader) -> ZomeApiResult<TimedPost> {
+    let post = match entry {
+        Entry::App(_, value) => serde_json::from_str::<Post>(&Into::<String>::into(value))
+            .map_err(|_| ZomeApiError::Internal("Entry was not a post".to_string()))?,
+        _ => return Err(ZomeApiError::Internal("Entry was not a post".to_string())),
+    };
+    let author = header
+        .provenances()
+        .first()
+        .map(|provenance| provenance.0.clone())
+        .unwrap_or_else(|| post.key_hash.clone());
+    Ok(TimedPost {
+        post,
+        address,
+        header_timestamp: header.timestamp().clone(),
+        author,
+        edited: header.link_update_delete().is_some(),
+    })
 }
 
-/// Update a post at address `old_address` with the entry `new_entry`
+/// Read a post along with its header timestamp and author
+fn handle_read_timed_post(address: Address) -> ZomeApiResult<TimedPost> {
+    match get_latest_with_header(&address)? {
+        Some((address, entry, header)) => to_timed_post(address, entry, header),
+        None => Err(ZomeApiError::Internal("No entry at this address".to_string())),
+    }
+}
+
+/// Get every revision of a post, oldest first
+///
+/// Once a post has been replaced by a tombstone every earlier revision is
+/// redacted as well, so nothing the post said before it was deleted is
+/// returned.
+fn handle_post_history(address: Address) -> ZomeApiResult<Vec<TimedPost>> {
+    let mut history = get_history_with_headers(&root_address(&address)?)?
+        .into_iter()
+        .map(|(address, entry, header)| to_timed_post(address, entry, header))
+        .collect::<ZomeApiResult<Vec<TimedPost>>>()?;
+    if history.iter().any(|timed_post| timed_post.post.deleted) {
+        for timed_post in &mut history {
+            timed_post.post.title = DELETED_TEXT.to_owned();
+            timed_post.post.content = DELETED_TEXT.to_owned();
+            timed_post.post.kind = PostKind::Text;
+            timed_post.post.attachments.clear();
+        }
+    }
+    Ok(history)
+}
+
+/// Returns `Ok(())` if a post marked `deleted` is a proper tombstone, with
+/// nothing of the post it replaced left in it
+fn validate_tombstone(post: &Post) -> Result<(), String> {
+    if !post.deleted {
+        Ok(())
+    } else if post.title != DELETED_TEXT || post.content != DELETED_TEXT {
+        Err(format!("Deleted posts must have {} as their title and content", DELETED_TEXT))
+    } else if !post.kind.is_text() || !post.attachments.is_empty() {
+        Err("Deleted posts cannot have a kind or attachments".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if `text` is short enough to be post metadata
+fn validate_metadata(name: &str, text: &str) -> Result<(), String> {
+    if text.len() > MAX_METADATA_LENGTH {
+        Err(format!("{} must be at most {} bytes", name, MAX_METADATA_LENGTH))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if `text` is at most `max_length` characters and has no
+/// control characters other than line breaks and tabs (or none at all if
+/// `!multiline`)
+fn validate_text(name: &str, text: &str, max_length: usize, multiline: bool) -> Result<(), String> {
+    if text.chars().count() > max_length {
+        Err(format!("{} must be at most {} characters", name, max_length))
+    } else if text
+        .chars()
+        .any(|c| c.is_control() && !(multiline && (c == '\n' || c == '\r' || c == '\t')))
+    {
+        Err(format!("{} cannot contain control characters", name))
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if the title and content of a post are within the limits
+/// set in the DNA properties
+fn validate_post_text(post: &Post, properties: &CometProperties) -> Result<(), String> {
+    if post.title.trim().is_empty() {
+        return Err("Post title cannot be empty".to_string());
+    }
+    validate_text("Post title", &post.title, properties.max_title_length, false)?;
+    validate_text("Post content", &post.content, properties.max_post_length, true)
+}
+
+/// Returns `Ok(())` if the kind specific parts of a post are valid and the
+/// kind is allowed by the DNA properties
+fn validate_kind(kind: &PostKind, properties: &CometProperties) -> Result<(), String> {
+    if !properties.allowed_post_kinds.iter().any(|allowed| allowed == kind.name()) {
+        return Err(format!("Posts of type {} are not allowed", kind.name()));
+    }
+    match kind {
+        PostKind::Text => Ok(()),
+        PostKind::Link { url, preview } => {
+            validate_url(url)?;
+            if let Some(preview) = preview {
+                if let Some(title) = &preview.title {
+                    validate_metadata("Preview title", title)?;
+                }
+                if let Some(description) = &preview.description {
+                    validate_metadata("Preview description", description)?;
+                }
+                if let Some(image_url) = &preview.image_url {
+                    validate_url(image_url)?;
+                }
+            }
+            Ok(())
+        }
+        PostKind::Image { url, manifest, alt_text } => {
+            match (url, manifest) {
+                (Some(url), None) => validate_url(url)?,
+                (None, Some(manifest)) => validate_image_manifest(manifest, "Image")?,
+                _ => return Err("Image posts need either a URL or a file manifest".to_string()),
+            }
+            match alt_text {
+                Some(alt_text) => validate_metadata("Alt text", alt_text),
+                None => Ok(()),
+            }
+        }
+        PostKind::File { url, name } => {
+            validate_url(url)?;
+            if name.is_empty() {
+                return Err("File name cannot be empty".to_string());
+            }
+            validate_metadata("File name", name)
+        }
+        PostKind::Poll => Ok(()),
+    }
+}
+
+/// Update the post at `old_address` with the entry `new_entry`
+///
+/// `old_address` can be the address of any version of the post, the latest
+/// version is the one replaced.
 fn handle_update_post(old_address: Address, new_entry: PostContent) -> ZomeApiResult<Address> {
-    let new_entry: Post = new_entry.into();
-    api::update_entry(Entry::App("post".into(), new_entry.into()), &old_address)
+    replace_post(old_address, new_entry.into())
 }
 
-/// Delete a post
-fn handle_delete_post(address: Address) -> ZomeApiResult<Address> {
-    let tags = handle_post_tags(address.clone())?;
-    for tag in tags.original_tags {
-        let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
-        api::remove_link(
-            &tag_anchor,
-            &address,
-            "original_tag_to_post",
-            "",
-        ).ok();
+/// Replace the latest version of the post at `old_address` with `new_post`
+fn replace_post(old_address: Address, new_post: Post) -> ZomeApiResult<Address> {
+    let latest_address = match get_latest_with_header(&old_address)? {
+        Some((latest_address, _, _)) => latest_address,
+        None => old_address,
+    };
+    api::update_entry(Entry::App("post".into(), new_post.into()), &latest_address)
+}
+
+/// Replace a post with a tombstone, hiding its title and content while
+/// keeping it, its tags and the comments and votes on it reachable
+fn handle_tombstone_post(address: Address, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    replace_post(
+        address,
+        Post {
+            title: DELETED_TEXT.to_owned(),
+            content: DELETED_TEXT.to_owned(),
+            key_hash: api::AGENT_ADDRESS.clone(),
+            timestamp: utc_unix_time.into(),
+            deleted: true,
+            kind: PostKind::Text,
+            attachments: Vec::new(),
+        },
+    )
+}
+
+/// Every link that is made for the post at `address`, whose first version is
+/// `post`, with the original tags `original_tags`, in both directions, along
+/// with the links of every crosspost of it
+///
+/// The author link keeps the tag it already has, so posts linked to their
+/// author before author links were tagged with `author_link_tag` keep their
+/// untagged link instead of getting a second one.
+fn post_links(address: &Address, post: &Post, original_tags: &[Tag]) -> ZomeApiResult<Vec<PostLink>> {
+    let link = |base: &Address, target: &Address, link_type: &str, tag: &str| PostLink {
+        base: base.clone(),
+        target: target.clone(),
+        link_type: link_type.to_owned(),
+        tag: tag.to_owned(),
+    };
+    let mut links: Vec<PostLink> =
+        api::get_links(&post.key_hash, LinkMatch::Exactly("post_author"), LinkMatch::Any)?
+            .links()
+            .into_iter()
+            .filter(|author_link| &author_link.address == address)
+            .map(|author_link| link(&post.key_hash, address, "post_author", &author_link.tag))
+            .collect();
+    if links.is_empty() {
+        let author_tag = author_link_tag(&post.timestamp).map_err(ZomeApiError::Internal)?;
+        links.push(link(&post.key_hash, address, "post_author", &author_tag));
     }
-    for tag in tags.crosspost_tags {
+    for tag in original_tags {
         let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
-        api::remove_link(
-            &tag_anchor,
-            &address,
-            "crosspost_tag_to_post",
-            "",
-        ).ok();
+        links.push(link(address, &tag_anchor, "post_to_original_tag", ""));
+        links.push(link(&tag_anchor, address, "original_tag_to_post", ""));
+    }
+    for (tag_anchor, crosspost) in get_crossposts(address)? {
+        let crossposter = crosspost.crossposter.map(String::from).unwrap_or_default();
+        links.push(link(address, &tag_anchor, "post_to_crosspost_tag", &crossposter));
+        links.push(link(&tag_anchor, address, "crosspost_tag_to_post", &crossposter));
+    }
+    Ok(links)
+}
+
+/// Whether `link` currently exists
+fn link_exists(link: &PostLink) -> ZomeApiResult<bool> {
+    Ok(api::get_links(
+        &link.base,
+        LinkMatch::Exactly(link.link_type.as_str()),
+        LinkMatch::Exactly(link.tag.as_str()),
+    )?
+    .addresses()
+    .contains(&link.target))
+}
+
+/// Get the first version of the post at `address`, even if it has been
+/// deleted
+///
+/// Only the initial entry is fetched, not the rest of its history, since
+/// this is also used in link validation.
+fn get_original_post(address: &Address) -> ZomeApiResult<Post> {
+    match api::get_entry_initial(address)? {
+        Some(Entry::App(entry_type, value)) => {
+            if String::from(entry_type) != "post" {
+                return Err(ZomeApiError::Internal("Entry is not a post".to_string()));
+            }
+            serde_json::from_str::<Post>(&Into::<String>::into(value))
+                .map_err(|_| ZomeApiError::Internal("Failed to parse post".to_string()))
+        }
+        Some(_) => Err(ZomeApiError::Internal("Entry is not a post".to_string())),
+        None => Err(ZomeApiError::Internal("No entry at this address".to_string())),
+    }
+}
+
+/// Delete a post along with its tag and author links
+///
+/// Every link is collected before any is removed. If some links cannot be
+/// removed the post entry is kept, and the failures are reported.
+fn handle_delete_post(address: Address) -> ZomeApiResult<DeleteReport> {
+    let address = root_address(&address)?;
+    let author = utils::get_as_type::<Post>(address.clone())?.key_hash;
+    if author != *api::AGENT_ADDRESS {
+        return Err(ZomeApiError::Internal("Cannot delete post that is not yours.".to_string()));
+    }
+    let post = get_original_post(&address)?;
+    let links = post_links(&address, &post, &handle_post_tags(address.clone())?.original_tags)?;
+
+    let failed_links: Vec<LinkFailure> = links
+        .into_iter()
+        .filter_map(|link| {
+            api::remove_link(&link.base, &link.target, link.link_type.as_str(), link.tag.as_str())
+                .err()
+                .map(|error| LinkFailure {
+                    link,
+                    error: error.to_string(),
+                })
+        })
+        .collect();
+
+    let deletion_address = if failed_links.is_empty() {
+        Some(api::remove_entry(&address)?)
+    } else {
+        None
+    };
+    Ok(DeleteReport {
+        deletion_address,
+        failed_links,
+    })
+}
+
+/// Links from tag anchors to the post at `address`, in both directions.
+///
+/// These are found from the anchors, so they are found even when the links
+/// from the post itself are gone, like after a delete that failed halfway.
+fn anchor_post_links(address: &Address) -> ZomeApiResult<Vec<PostLink>> {
+    #[derive(Serialize, Deserialize, Debug, DefaultJson)]
+    struct AnchorsCallType {
+        anchor_type: String,
+    }
+    let json_string: String = api::call(
+        hdk::THIS_INSTANCE,
+        "anchors",
+        Address::from(api::PUBLIC_TOKEN.to_string()),
+        "anchors",
+        (AnchorsCallType { anchor_type: "tag".to_owned() }).into(),
+    )?
+    .into();
+    let tag_anchors = serde_json::from_str::<ZomeApiResult<GetLinksResult>>(&json_string)
+        .map_err(|_| ZomeApiError::Internal("Failed to deserialize anchors result".to_owned()))??
+        .addresses();
+
+    let mut links = Vec::new();
+    for tag_anchor in tag_anchors {
+        for &(link_type, reverse_link_type) in &[
+            ("original_tag_to_post", "post_to_original_tag"),
+            ("crosspost_tag_to_post", "post_to_crosspost_tag"),
+        ] {
+            for link in api::get_links(&tag_anchor, LinkMatch::Exactly(link_type), LinkMatch::Any)?.links() {
+                if &link.address == address {
+                    links.push(PostLink {
+                        base: tag_anchor.clone(),
+                        target: address.clone(),
+                        link_type: link_type.to_string(),
+                        tag: link.tag.clone(),
+                    });
+                    links.push(PostLink {
+                        base: address.clone(),
+                        target: tag_anchor.clone(),
+                        link_type: reverse_link_type.to_string(),
+                        tag: link.tag,
+                    });
+                }
+            }
+        }
     }
-    api::remove_link(
-        &utils::get_as_type::<Post>(address.clone())?.key_hash,
-        &address,
-        "post_author",
-        "",
-    ).ok();
-    api::remove_entry(&address)
+    Ok(links)
 }
 
-/// Determine if a post to anchor link is valid. Returns
-/// `Ok(())` if it is, `Err(e)` when it's not where `e`
+/// Find and fix dangling links of a post.
+///
+/// Links to deleted posts are removed. Links of live posts that are only
+/// present in one direction get their other direction added back. Links are
+/// looked for from both the post and every tag anchor.
+fn handle_repair_post(address: Address) -> ZomeApiResult<RepairReport> {
+    let address = root_address(&address)?;
+    let post = get_original_post(&address)?;
+    let deleted = api::get_entry(&address)?.is_none();
+    let mut links = post_links(&address, &post, &handle_post_tags(address.clone())?.original_tags)?;
+    for link in anchor_post_links(&address)? {
+        if !links.contains(&link) {
+            links.push(link);
+        }
+    }
+
+    let mut report = RepairReport {
+        added_links: Vec::new(),
+        removed_links: Vec::new(),
+        failed_links: Vec::new(),
+    };
+    for link in links {
+        let exists = link_exists(&link)?;
+        let result = if deleted && exists {
+            api::remove_link(&link.base, &link.target, link.link_type.as_str(), link.tag.as_str())
+                .map(|_| report.removed_links.push(link.clone()))
+        } else if !deleted && !exists {
+            api::link_entries(&link.base, &link.target, link.link_type.as_str(), link.tag.as_str())
+                .map(|_| report.added_links.push(link.clone()))
+        } else {
+            Ok(())
+        };
+        if let Err(error) = result {
+            report.failed_links.push(LinkFailure {
+                link,
+                error: error.to_string(),
+            });
+        }
+    }
+    Ok(report)
+}
+
+/// Determine if a link between a post and one of its original tags is
+/// valid. Returns `Ok(())` if it is, `Err(e)` when it's not where `e`
 /// is a `String` detailing why it is not valid.
+///
+/// Only the author of the post, going by its `key_hash`, can add or remove
+/// these links. `header` is the chain header of the link.
 fn post_anchor_link_valid(
-    post_address: Address,
-    anchor_address: Address,
-    source_chain_headers: Option<Vec<ChainHeader>>,
-    must_be_author: bool,
+    post_address: &Address,
+    anchor_address: &Address,
+    header: &ChainHeader,
 ) -> Result<(), String> {
-    // TODO: Better way to find if post is in source chain
-    match source_chain_headers {
-        Some(headers) => {
-            if !must_be_author
-                || headers
-                    .iter()
-                    .map(|header| header.entry_address())
-                    .any(|entry_address| entry_address == &post_address)
-            {
-                match utils::get_as_type::<Anchor>(anchor_address) {
-                    Ok(anchor) => match serde_json::from_str::<Tag>(&anchor.anchor_text) {
-                        Ok(_) => Ok(()),
-                        Err(_) => Err("`anchor_text` is not a valid tag.".to_owned()),
-                    },
-                    Err(_) => Err("Error getting link entry.".to_owned()),
-                }
+    let post = get_original_post(post_address).map_err(|_| "Link was not post".to_owned())?;
+    if header.provenances().iter().all(|provenance| provenance.0 == post.key_hash) {
+        link_tag(anchor_address.clone()).map(|_| ())
+    } else {
+        Err("Cannot link post to tag from author not in `key_hash`".to_owned())
+    }
+}
+
+/// Get the tag of the anchor at `anchor_address` for link validation
+fn link_tag(anchor_address: Address) -> Result<Tag, String> {
+    match utils::get_as_type::<Anchor>(anchor_address) {
+        Ok(anchor) => {
+            if anchor.anchor_type != "tag" {
+                return Err("Link must be to a tag anchor.".to_owned());
+            }
+            serde_json::from_str::<Tag>(&anchor.anchor_text)
+                .map_err(|_| "`anchor_text` is not a valid tag.".to_owned())
+        }
+        Err(_) => Err("Error getting link entry.".to_owned()),
+    }
+}
+
+/// Determine if a crosspost link between a post and a tag anchor is valid.
+///
+/// Crosspost links are tagged with the agent who made them (`crossposter`),
+/// and only that agent (from `header`) can add them. They can be removed by
+/// that agent or by the author of the post, so that authors can always
+/// delete their posts, even of crossposts made before they were tagged. The
+/// anchor must
+/// be a valid tag. When `adding`, the post must also be a `post` entry and
+/// the policy of the tag in the DNA properties must allow the agent to
+/// crosspost the post into it.
+fn crosspost_link_valid(
+    post_address: &Address,
+    anchor_address: &Address,
+    crossposter: &str,
+    header: &ChainHeader,
+    adding: bool,
+) -> Result<(), String> {
+    let by = |agent: &str| {
+        !agent.is_empty()
+            && header
+                .provenances()
+                .iter()
+                .all(|provenance| String::from(provenance.0.clone()) == agent)
+    };
+    let tag = link_tag(anchor_address.clone())?;
+    if !adding {
+        if by(crossposter) {
+            return Ok(());
+        }
+        return match api::get_entry_initial(post_address) {
+            Ok(Some(Entry::App(_, value))) => match serde_json::from_str::<Post>(&Into::<String>::into(value)) {
+                Ok(ref post) if by(&String::from(post.key_hash.clone())) => Ok(()),
+                _ => Err("Cannot remove crosspost that is not yours.".to_string()),
+            },
+            _ => Err("Cannot remove crosspost that is not yours.".to_string()),
+        };
+    }
+    if !by(crossposter) {
+        return Err("Crossposts must be tagged with the agent who made them".to_string());
+    }
+    let post = match api::get_entry(post_address) {
+        Ok(Some(Entry::App(entry_type, value))) => {
+            if String::from(entry_type) != "post" {
+                return Err("Crossposts must be of posts.".to_owned());
+            }
+            serde_json::from_str::<Post>(&Into::<String>::into(value))
+                .map_err(|_| "Failed to parse crossposted post.".to_owned())?
+        }
+        _ => return Err("Crossposts must be of posts.".to_owned()),
+    };
+
+    let policy = CometProperties::load()?
+        .crosspost_policies
+        .get(&tag)
+        .cloned()
+        .unwrap_or(CrosspostPolicy::Open);
+    match policy {
+        CrosspostPolicy::Open => Ok(()),
+        CrosspostPolicy::AuthorOnly => {
+            if header.provenances().iter().all(|provenance| provenance.0 == post.key_hash) {
+                Ok(())
             } else {
-                Err("Could not find post in chain.".to_owned())
+                Err(format!("Only the authors of posts can crosspost them into tag {}", tag))
             }
         }
-        None => Err("Internal error: Invalid validation package.".to_owned()),
+        CrosspostPolicy::Closed => Err(format!("Posts cannot be crossposted into tag {}", tag)),
+    }
+}
+
+/// The link of `link_type` added (`1`) or removed (`-1`) by a link entry, if
+/// `entry` is one
+fn link_change<'a>(entry: &'a Entry, link_type: &str) -> Option<(&'a Link, i64)> {
+    let (link_data, change) = match entry {
+        Entry::LinkAdd(link_data) => (link_data, 1),
+        Entry::LinkRemove((link_data, _)) => (link_data, -1),
+        _ => return None,
+    };
+    if link_data.link().link_type() == link_type {
+        Some((link_data.link(), change))
+    } else {
+        None
+    }
+}
+
+/// The post and the tag anchor a link of `link_type` between them is made
+/// from, whichever direction it goes in
+fn post_and_anchor<'a>(link: &'a Link, link_type: &str) -> (&'a Address, &'a Address) {
+    if link_type.starts_with("post_to_") {
+        (link.base(), link.target())
+    } else {
+        (link.target(), link.base())
+    }
+}
+
+/// Returns `Ok(())` if adding a link of `link_type` between `post_address`
+/// and the original tag at `anchor_address` keeps the post within the tags
+/// per post allowed by the DNA properties.
+///
+/// Only the author of a post can link it to its original tags, so the
+/// existing tags are counted from the links of the same type in the author's
+/// source chain entries (from `ValidationPackageDefinition::ChainEntries`).
+/// Both directions are counted separately, since search reads the links
+/// from the anchors.
+///
+/// Unlike the authorship check in `post_anchor_link_valid`, this is not
+/// constant-size: a limit on what an agent did before can only be checked
+/// the same way by every validator from that agent's chain. Counting the
+/// links on the DHT instead would be bounded, but validators could see
+/// different links and a spammer could race the limit. Only tag links pay
+/// for this, and only when they are added.
+fn original_tag_count_valid(
+    post_address: &Address,
+    anchor_address: &Address,
+    link_type: &str,
+    source_chain_entries: &Option<Vec<Entry>>,
+) -> Result<(), String> {
+    let entries = source_chain_entries
+        .as_ref()
+        .ok_or_else(|| "Internal error: Invalid validation package.".to_owned())?;
+    let max_tags = CometProperties::load()?.max_tags_per_post;
+    let mut net_links: HMap<Address, i64> = HMap::new();
+    for (link, change) in entries.iter().filter_map(|entry| link_change(entry, link_type)) {
+        let (post, anchor) = post_and_anchor(link, link_type);
+        if post == post_address && anchor != anchor_address {
+            *net_links.entry(anchor.clone()).or_insert(0) += change;
+        }
+    }
+    let tags = net_links.values().filter(|count| **count > 0).count();
+    if tags >= max_tags {
+        Err(format!("Posts can have at most {} tags", max_tags))
+    } else {
+        Ok(())
     }
 }
 
+/// Returns `Ok(())` if the author of a new crosspost link of `link_type`
+/// between `post_address` and the tag at `anchor_address` is within the
+/// crossposts per post and per hour allowed by the DNA properties.
+///
+/// Earlier crossposts are the links of the same type in the author's source
+/// chain, from the headers and entries of a
+/// `ValidationPackageDefinition::ChainFull` package, so every validator
+/// counts the same links. The headers are needed for the hourly limit. See
+/// `original_tag_count_valid` for why this is not constant-size. `header` is
+/// the chain header of the new link.
+fn crosspost_count_valid(
+    post_address: &Address,
+    anchor_address: &Address,
+    link_type: &str,
+    header: &ChainHeader,
+    source_chain_headers: &Option<Vec<ChainHeader>>,
+    source_chain_entries: &Option<Vec<Entry>>,
+) -> Result<(), String> {
+    let (headers, entries) = match (source_chain_headers, source_chain_entries) {
+        (Some(headers), Some(entries)) => (headers, entries),
+        _ => return Err("Internal error: Invalid validation package.".to_owned()),
+    };
+    let entries: HMap<Address, &Entry> = entries.iter().map(|entry| (entry.address(), entry)).collect();
+    let properties = CometProperties::load()?;
+    let now = unix_time(header.timestamp())?;
+
+    let mut recent = 0;
+    let mut net_links: HMap<(Address, Address), i64> = HMap::new();
+    for header in headers {
+        let (link, change) = match entries
+            .get(header.entry_address())
+            .and_then(|entry| link_change(entry, link_type))
+        {
+            Some(link_change) => link_change,
+            None => continue,
+        };
+        let (post, anchor) = post_and_anchor(link, link_type);
+        *net_links.entry((post.clone(), anchor.clone())).or_insert(0) += change;
+        if change > 0 && now - unix_time(header.timestamp())? < 60 * 60 {
+            recent += 1;
+        }
+    }
+
+    let crossposts = net_links
+        .iter()
+        .filter(|((post, anchor), count)| post == post_address && anchor != anchor_address && **count > 0)
+        .count();
+    if crossposts >= properties.max_crosspost_count {
+        Err(format!(
+            "You can crosspost a post to at most {} tags",
+            properties.max_crosspost_count
+        ))
+    } else if recent >= properties.max_crossposts_per_hour {
+        Err(format!(
+            "You can crosspost at most {} times an hour",
+            properties.max_crossposts_per_hour
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// Get the crossposts of the post at `address`, along with the address of
+/// the anchor of each tag
+fn get_crossposts(address: &Address) -> ZomeApiResult<Vec<(Address, Crosspost)>> {
+    Ok(api::get_links(address, LinkMatch::Exactly("post_to_crosspost_tag"), LinkMatch::Any)?
+        .links()
+        .into_iter()
+        .filter_map(|link| {
+            let anchor = utils::get_as_type::<Anchor>(link.address.clone()).ok()?;
+            let tag = serde_json::from_str::<Tag>(&anchor.anchor_text).ok()?;
+            let crossposter = if link.tag.is_empty() {
+                None
+            } else {
+                Some(Address::from(link.tag))
+            };
+            Some((link.address, Crosspost { tag, crossposter }))
+        })
+        .collect())
+}
+
 /// "Crosspost" a post to a set of tags
 /// Return Ok(()) if the action completely successfully
+///
+/// The crosspost links are tagged with the address of the agent, so that
+/// they can be attributed and removed by them later.
+///
+/// Nothing is linked if the agent would end up crossposting the post to more
+/// tags than the DNA properties allow. The hourly limit is only checked in
+/// validation.
 fn handle_crosspost(post_address: Address, tags: Vec<Tag>) -> ZomeApiResult<()> {
+    let post_address = root_address(&post_address)?;
+    let max_crossposts = CometProperties::load().map_err(ZomeApiError::Internal)?.max_crosspost_count;
+    let crossposted: HashSet<Tag> = get_crossposts(&post_address)?
+        .into_iter()
+        .filter(|(_, crosspost)| crosspost.crossposter.as_ref() == Some(&*api::AGENT_ADDRESS))
+        .map(|(_, crosspost)| crosspost.tag)
+        .collect();
+    let added = tags.iter().filter(|tag| !crossposted.contains(tag)).collect::<HashSet<_>>().len();
+    if crossposted.len() + added > max_crossposts {
+        return Err(ZomeApiError::Internal(format!(
+            "You can crosspost a post to at most {} tags",
+            max_crossposts
+        )));
+    }
     for tag in tags {
         let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
-        utils::link_entries_bidir(&tag_anchor, &post_address, "crosspost_tag_to_post", "post_to_crosspost_tag", "", "")?;
+        handle_crosspost_raw(post_address.clone(), tag_anchor)?;
+    }
+    Ok(())
+}
+
+/// Crosspost a post to the anchor at `anchor_address` without checking that
+/// it is a tag anchor. The links are still validated.
+fn handle_crosspost_raw(post_address: Address, anchor_address: Address) -> ZomeApiResult<()> {
+    let post_address = root_address(&post_address)?;
+    let crossposter = String::from(api::AGENT_ADDRESS.clone());
+    utils::link_entries_bidir(
+        &anchor_address,
+        &post_address,
+        "crosspost_tag_to_post",
+        "post_to_crosspost_tag",
+        crossposter.as_str(),
+        crossposter.as_str(),
+    )?;
+    Ok(())
+}
+
+/// List who crossposted a post to which tags
+fn handle_crossposts_of(post_address: Address) -> ZomeApiResult<Vec<Crosspost>> {
+    Ok(get_crossposts(&root_address(&post_address)?)?
+        .into_iter()
+        .map(|(_, crosspost)| crosspost)
+        .collect())
+}
+
+/// Undo a crosspost of a post to `tag` made by this agent
+fn handle_remove_crosspost(post_address: Address, tag: Tag) -> ZomeApiResult<()> {
+    let post_address = root_address(&post_address)?;
+    let is_mine = get_crossposts(&post_address)?
+        .iter()
+        .any(|(_, crosspost)| crosspost.tag == tag && crosspost.crossposter.as_ref() == Some(&*api::AGENT_ADDRESS));
+    if !is_mine {
+        return Err(ZomeApiError::Internal("No crosspost of yours in that tag".to_string()));
+    }
+    let crossposter = String::from(api::AGENT_ADDRESS.clone());
+    let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+    api::remove_link(&post_address, &tag_anchor, "post_to_crosspost_tag", crossposter.as_str())?;
+    api::remove_link(&tag_anchor, &post_address, "crosspost_tag_to_post", crossposter.as_str())
+}
+
+/// Change the original tags of a post, adding it to the tags in `add` and
+/// removing it from the tags in `remove`
+/// Return Ok(()) if the action completely successfully
+///
+/// Only the author of a post can retag it. Tags in both `add` and `remove`
+/// end up added.
+fn handle_retag_post(address: Address, add: Vec<Tag>, remove: Vec<Tag>) -> ZomeApiResult<()> {
+    let address = root_address(&address)?;
+    if utils::get_as_type::<Post>(address.clone())?.key_hash != *api::AGENT_ADDRESS {
+        return Err(ZomeApiError::Internal("Cannot retag post that is not yours.".to_string()));
+    }
+    let original_tags = handle_post_tags(address.clone())?.original_tags;
+    let max_tags = CometProperties::load().map_err(ZomeApiError::Internal)?.max_tags_per_post;
+    let added = add.iter().filter(|tag| !original_tags.contains(tag)).collect::<HashSet<_>>().len();
+    let removed = remove
+        .iter()
+        .filter(|tag| original_tags.contains(tag) && !add.contains(tag))
+        .collect::<HashSet<_>>()
+        .len();
+    if original_tags.len() + added - removed > max_tags {
+        return Err(ZomeApiError::Internal(format!("Posts can have at most {} tags", max_tags)));
+    }
+    handle_retag_post_raw(address, add, remove)
+}
+
+/// Change the original tags of a post without checking who is retagging it
+/// or how many tags it ends up with. The links are still validated.
+fn handle_retag_post_raw(address: Address, add: Vec<Tag>, remove: Vec<Tag>) -> ZomeApiResult<()> {
+    let address = root_address(&address)?;
+    let mut original_tags = handle_post_tags(address.clone())?.original_tags;
+    for tag in remove {
+        if original_tags.contains(&tag) {
+            let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+            api::remove_link(&address, &tag_anchor, "post_to_original_tag", "")?;
+            api::remove_link(&tag_anchor, &address, "original_tag_to_post", "")?;
+            original_tags.retain(|original_tag| original_tag != &tag);
+        }
+    }
+    for tag in add {
+        if !original_tags.contains(&tag) {
+            let tag_anchor = anchor("tag".to_owned(), tag.to_string())?;
+            utils::link_entries_bidir(
+                &address,
+                &tag_anchor,
+                "post_to_original_tag",
+                "original_tag_to_post",
+                "",
+                "",
+            )?;
+            original_tags.push(tag);
+        }
     }
     Ok(())
 }
@@ -428,6 +1406,7 @@ fn handle_post_tags(address: Address) -> ZomeApiResult<PostTags> {
             .collect()
     }
 
+    let address = root_address(&address)?;
     match (
         api::get_links(&address, LinkMatch::Exactly("post_to_original_tag"), LinkMatch::Any),
         api::get_links(&address, LinkMatch::Exactly("post_to_crosspost_tag"), LinkMatch::Any),
@@ -442,8 +1421,34 @@ fn handle_post_tags(address: Address) -> ZomeApiResult<PostTags> {
 }
 
 /// Return the addresses of posts a user has made by their key address
-fn handle_user_posts(author: Address) -> ZomeApiResult<Vec<Address>> {
-    api::get_links(&author, LinkMatch::Exactly("post_author"), LinkMatch::Any).map(|links| links.addresses())
+///
+/// These are the addresses of the first version of each post. When
+/// `apply_filters` is set, posts blocked by the calling agent are left out.
+fn handle_user_posts(author: Address, apply_filters: bool) -> ZomeApiResult<Vec<Address>> {
+    let posts = api::get_links(&author, LinkMatch::Exactly("post_author"), LinkMatch::Any)?.addresses();
+    if !apply_filters {
+        return Ok(posts);
+    }
+    unblocked_posts(posts)
+}
+
+/// Get a page of the posts and comments made by `agent`, newest first. Pages
+/// start at 0 and hold `PAGE_SIZE` items.
+///
+/// Items are ordered by the time in their author links, so only the items on
+/// the page are fetched.
+fn handle_user_activity(agent: Address, page: u32) -> ZomeApiResult<Vec<Activity>> {
+    let mut activity = Vec::new();
+    for (link_type, address) in authored_page(&agent, &["post_author", "comment_author"], page, PAGE_SIZE)? {
+        if let Some(header) = initial_header(&address)? {
+            activity.push(Activity {
+                address,
+                entry_type: if link_type == "post_author" { "post" } else { "comment" }.to_string(),
+                header_timestamp: header.timestamp().clone(),
+            });
+        }
+    }
+    Ok(activity)
 }
 
 /// Username of an agent. Used instead of string to get around issues of
@@ -467,8 +1472,66 @@ fn handle_get_agent_address() -> Address {
     api::AGENT_ADDRESS.clone()
 }
 
+/// Get the network-wide configuration from the DNA properties
+fn handle_get_properties() -> ZomeApiResult<CometProperties> {
+    CometProperties::load().map_err(ZomeApiError::Internal)
+}
+
 define_zome! {
     entries: [
+        entry!(
+            name: "tag_creator",
+            description: "Claim to be the creator of a tag",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<TagCreator>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: claim,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| provenance.0 == claim.key_hash) {
+                            return Err("Cannot claim a tag for another agent.".to_string());
+                        }
+                        validate_tag_creator(&claim)?;
+                        validate_timestamp(&claim.timestamp, &validation_data.package.chain_header)
+                    }
+                    _ => Err("Tag claims cannot be changed".to_string()),
+                }
+            },
+            links: [
+                from!(
+                    "anchor",
+                    link_type: "tag_creator",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        let claim = utils::get_as_type::<TagCreator>(link.link().target().clone())
+                            .map_err(|_| "Link was not tag creator".to_string())?;
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| provenance.0 == claim.key_hash) {
+                            return Err("Tag claims must be linked by their claimant".to_string());
+                        }
+                        if link_tag(link.link().base().clone())? == claim.tag {
+                            Ok(())
+                        } else {
+                            Err("Tag claims must be linked from the anchor of their tag".to_string())
+                        }
+                    }
+                )
+            ]
+        ),
         entry!(
             name: "post",
             description: "User post",
@@ -484,7 +1547,14 @@ define_zome! {
                     } => {
                         let provenances = validation_data.package.chain_header.provenances();
                         if provenances.iter().all(|provenance| provenance.0 == post.key_hash) {
-                            Ok(())
+                            if post.deleted {
+                                return Err("Posts cannot be created deleted.".to_string());
+                            }
+                            let properties = CometProperties::load()?;
+                            validate_post_text(&post, &properties)?;
+                            validate_kind(&post.kind, &properties)?;
+                            validate_attachments(&post.attachments)?;
+                            validate_timestamp(&post.timestamp, &validation_data.package.chain_header)
                         } else {
                             not_ok
                         }
@@ -501,7 +1571,15 @@ define_zome! {
                         if old_post.key_hash == new_post.key_hash
                             && provenances.all(|provenance| provenance.0 == old_post.key_hash)
                         {
-                            Ok(())
+                            if old_post.deleted {
+                                return Err("Cannot alter post that has been deleted.".to_string());
+                            }
+                            validate_tombstone(&new_post)?;
+                            let properties = CometProperties::load()?;
+                            validate_post_text(&new_post, &properties)?;
+                            validate_kind(&new_post.kind, &properties)?;
+                            validate_attachments(&new_post.attachments)?;
+                            validate_timestamp(&new_post.timestamp, &validation_data.package.chain_header)
                         } else {
                             not_ok
                         }
@@ -528,106 +1606,144 @@ define_zome! {
                 to!(
                     "anchor",
                     link_type: "post_to_original_tag",
-                    validation_package: || hdk::ValidationPackageDefinition::ChainHeaders,
+                    validation_package: || hdk::ValidationPackageDefinition::ChainEntries,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let (link, validation_data) = match link_validation_data {
+                        let (link, validation_data, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, false),
                         };
                         if link.link.tag() != "" {
                             return Err("Tag must be the empty string".to_string());
                         }
                         post_anchor_link_valid(
-                            link.link().base().clone(),
-                            link.link().target().clone(),
-                            validation_data.package.source_chain_headers,
-                            true,
-                        )
+                            link.link().base(),
+                            link.link().target(),
+                            &validation_data.package.chain_header,
+                        )?;
+                        if adding {
+                            original_tag_count_valid(
+                                link.link().base(),
+                                link.link().target(),
+                                "post_to_original_tag",
+                                &validation_data.package.source_chain_entries,
+                            )
+                        } else {
+                            Ok(())
+                        }
                     }
                 ),
                 from!(
                     "anchor",
                     link_type: "original_tag_to_post",
-                    validation_package: || hdk::ValidationPackageDefinition::ChainHeaders,
+                    validation_package: || hdk::ValidationPackageDefinition::ChainEntries,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let (link, validation_data) = match link_validation_data {
+                        let (link, validation_data, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, false),
                         };
                         if link.link.tag() != "" {
                             return Err("Tag must be the empty string".to_string());
                         }
                         post_anchor_link_valid(
-                            link.link().target().clone(),
-                            link.link().base().clone(),
-                            validation_data.package.source_chain_headers,
-                            true,
-                        )
+                            link.link().target(),
+                            link.link().base(),
+                            &validation_data.package.chain_header,
+                        )?;
+                        if adding {
+                            original_tag_count_valid(
+                                link.link().target(),
+                                link.link().base(),
+                                "original_tag_to_post",
+                                &validation_data.package.source_chain_entries,
+                            )
+                        } else {
+                            Ok(())
+                        }
                     }
                 ),
                 // Posts link to and from crossposted tags
                 to!(
                     "anchor",
                     link_type: "post_to_crosspost_tag",
-                    validation_package: || hdk::ValidationPackageDefinition::ChainHeaders,
+                    validation_package: || hdk::ValidationPackageDefinition::ChainFull,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let (link, validation_data) = match link_validation_data {
+                        let (link, validation_data, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, false),
                         };
-                        if link.link.tag() != "" {
-                            return Err("Tag must be the empty string".to_string());
+                        crosspost_link_valid(
+                            link.link().base(),
+                            link.link().target(),
+                            link.link().tag(),
+                            &validation_data.package.chain_header,
+                            adding,
+                        )?;
+                        if adding {
+                            crosspost_count_valid(
+                                link.link().base(),
+                                link.link().target(),
+                                "post_to_crosspost_tag",
+                                &validation_data.package.chain_header,
+                                &validation_data.package.source_chain_headers,
+                                &validation_data.package.source_chain_entries,
+                            )
+                        } else {
+                            Ok(())
                         }
-                        post_anchor_link_valid(
-                            link.link().base().clone(),
-                            link.link().target().clone(),
-                            validation_data.package.source_chain_headers,
-                            false,
-                        )
                     }
                 ),
                 from!(
                     "anchor",
                     link_type: "crosspost_tag_to_post",
-                    validation_package: || hdk::ValidationPackageDefinition::ChainHeaders,
+                    validation_package: || hdk::ValidationPackageDefinition::ChainFull,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let (link, validation_data) = match link_validation_data {
+                        let (link, validation_data, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => (link, validation_data),
+                            } => (link, validation_data, false),
                         };
-                        if link.link.tag() != "" {
-                            return Err("Tag must be the empty string".to_string());
+                        crosspost_link_valid(
+                            link.link().target(),
+                            link.link().base(),
+                            link.link().tag(),
+                            &validation_data.package.chain_header,
+                            adding,
+                        )?;
+                        if adding {
+                            crosspost_count_valid(
+                                link.link().target(),
+                                link.link().base(),
+                                "crosspost_tag_to_post",
+                                &validation_data.package.chain_header,
+                                &validation_data.package.source_chain_headers,
+                                &validation_data.package.source_chain_entries,
+                            )
+                        } else {
+                            Ok(())
                         }
-                        post_anchor_link_valid(
-                            link.link().target().clone(),
-                            link.link().base().clone(),
-                            validation_data.package.source_chain_headers,
-                            false,
-                        )
                     }
                 ),
                 // Posts links from (to implicit by `key_hash` field) their author's key hash
@@ -636,22 +1752,28 @@ define_zome! {
                     link_type: "post_author",
                     validation_package: || hdk::ValidationPackageDefinition::Entry,
                     validation: |link_validation_data: hdk::LinkValidationData| {
-                        let link = match link_validation_data {
+                        let (link, validation_data, adding) = match link_validation_data {
                             LinkValidationData::LinkAdd {
                                 link,
                                 validation_data,
-                            } => link,
+                            } => (link, validation_data, true),
                             LinkValidationData::LinkRemove {
                                 link,
                                 validation_data,
-                            } => link,
+                            } => (link, validation_data, false),
                         };
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| &provenance.0 == link.link().base()) {
+                            return Err("Cannot link to post from author not in `key_hash`".to_owned());
+                        }
                         match utils::get_as_type::<Post>(link.link().target().clone()) {
                             Ok(post) => {
-                                if &post.key_hash == link.link().base() {
-                                    Ok(())
-                                } else {
+                                if &post.key_hash != link.link().base() {
                                     Err("Cannot link to post from author not in `key_hash`".to_owned())
+                                } else if adding && link.link().tag() != &author_link_tag(&post.timestamp)? {
+                                    Err("Author links must be tagged with the time of the post".to_owned())
+                                } else {
+                                    Ok(())
                                 }
                             },
                             Err(_) => Err("Link was not post".to_owned())
@@ -681,21 +1803,41 @@ define_zome! {
         }
         read_post: {
             inputs: |address: Address|,
-            outputs: |post: ZomeApiResult<Post>|,
+            outputs: |post: ZomeApiResult<LatestPost>|,
             handler: handle_read_post
         }
+        read_timed_post: {
+            inputs: |address: Address|,
+            outputs: |post: ZomeApiResult<TimedPost>|,
+            handler: handle_read_timed_post
+        }
+        post_history: {
+            inputs: |address: Address|,
+            outputs: |history: ZomeApiResult<Vec<TimedPost>>|,
+            handler: handle_post_history
+        }
         update_post: {
             inputs: |old_address: Address, new_entry: PostContent|,
             outputs: |new_post: ZomeApiResult<Address>|,
             handler: handle_update_post
         }
+        tombstone_post: {
+            inputs: |address: Address, utc_unix_time: u64|,
+            outputs: |new_post: ZomeApiResult<Address>|,
+            handler: handle_tombstone_post
+        }
         delete_post: {
             inputs: |address: Address|,
-            outputs: |ok: ZomeApiResult<Address>|,
+            outputs: |report: ZomeApiResult<DeleteReport>|,
             handler: handle_delete_post
         }
+        repair_post: {
+            inputs: |address: Address|,
+            outputs: |report: ZomeApiResult<RepairReport>|,
+            handler: handle_repair_post
+        }
         search: {
-            inputs: |query: Search, exclude_crossposts: bool|,
+            inputs: |query: Search, exclude_crossposts: bool, apply_filters: bool, respect_moderation: bool|,
             outputs: |result: ZomeApiResult<Vec<SearchResult>>|,
             handler: handle_search
         }
@@ -704,16 +1846,46 @@ define_zome! {
             outputs: |ok: ZomeApiResult<()>|,
             handler: handle_crosspost
         }
+        crosspost_raw: {
+            inputs: |post_address: Address, anchor_address: Address|,
+            outputs: |ok: ZomeApiResult<()>|,
+            handler: handle_crosspost_raw
+        }
+        crossposts_of: {
+            inputs: |post_address: Address|,
+            outputs: |result: ZomeApiResult<Vec<Crosspost>>|,
+            handler: handle_crossposts_of
+        }
+        remove_crosspost: {
+            inputs: |post_address: Address, tag: Tag|,
+            outputs: |result: ZomeApiResult<()>|,
+            handler: handle_remove_crosspost
+        }
+        retag_post: {
+            inputs: |address: Address, add: Vec<Tag>, remove: Vec<Tag>|,
+            outputs: |ok: ZomeApiResult<()>|,
+            handler: handle_retag_post
+        }
+        retag_post_raw: {
+            inputs: |address: Address, add: Vec<Tag>, remove: Vec<Tag>|,
+            outputs: |ok: ZomeApiResult<()>|,
+            handler: handle_retag_post_raw
+        }
         post_tags: {
             inputs: |address: Address|,
             outputs: |tags: ZomeApiResult<PostTags>|,
             handler: handle_post_tags
         }
         user_posts: {
-            inputs: |author: Address|,
+            inputs: |author: Address, apply_filters: bool|,
             outputs: |posts: ZomeApiResult<Vec<Address>>|,
             handler: handle_user_posts
         }
+        user_activity: {
+            inputs: |agent: Address, page: u32|,
+            outputs: |activity: ZomeApiResult<Vec<Activity>>|,
+            handler: handle_user_activity
+        }
         get_username: {
             inputs: |agent_address: Address|,
             outputs: |username: ZomeApiResult<Username>|,
@@ -724,6 +1896,11 @@ define_zome! {
             outputs: |agent_address: Address|,
             handler: handle_get_agent_address
         }
+        get_properties: {
+            inputs: | |,
+            outputs: |properties: ZomeApiResult<CometProperties>|,
+            handler: handle_get_properties
+        }
     ]
 
     traits: {
@@ -731,14 +1908,25 @@ define_zome! {
             create_post,
             create_post_raw,
             read_post,
+            read_timed_post,
+            post_history,
             update_post,
+            tombstone_post,
             delete_post,
+            repair_post,
             search,
             crosspost,
+            crosspost_raw,
+            crossposts_of,
+            remove_crosspost,
+            retag_post,
+            retag_post_raw,
             post_tags,
             user_posts,
+            user_activity,
             get_username,
-            get_agent_address
+            get_agent_address,
+            get_properties
         ]
     }
 }
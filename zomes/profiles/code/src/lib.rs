@@ -0,0 +1,293 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate comet_common;
+
+use hdk::api;
+use hdk::{EntryValidationData, LinkValidationData};
+use hdk::error::{ZomeApiError, ZomeApiResult};
+use hdk::holochain_core_types::{
+    dna::entry_types::Sharing, entry::Entry,
+    time::Iso8601, link::LinkMatch,
+};
+use hdk::holochain_persistence_api::cas::content::Address;
+use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
+use hdk::utils;
+use hdk::ValidationPackageDefinition;
+use comet_common::{created_at, latest_address, validate_image_manifest, validate_timestamp, validate_url};
+
+/// Longest display name, in characters
+const MAX_DISPLAY_NAME_LENGTH: usize = 64;
+
+/// Longest bio, in characters
+const MAX_BIO_LENGTH: usize = 1000;
+
+/// Most links a profile can have
+const MAX_LINKS: usize = 8;
+
+/// Public profile of an agent. New versions are published with
+/// `update_entry`.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct Profile {
+    display_name: String,
+    bio: String,
+    /// Address of the manifest of an image (from the `files` zome)
+    avatar: Option<Address>,
+    links: Vec<String>,
+    key_hash: Address,
+    timestamp: Iso8601,
+}
+
+/// Profile of an agent as shown to others.
+///
+/// Agents that never set a profile are shown with their nick as their
+/// display name.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct AgentProfile {
+    agent: Address,
+    display_name: String,
+    bio: String,
+    avatar: Option<Address>,
+    links: Vec<String>,
+}
+
+/// Returns `Ok(())` if a profile's display name, bio, avatar and links are
+/// valid
+fn validate_profile(profile: &Profile) -> Result<(), String> {
+    if profile.display_name.trim().is_empty() {
+        return Err("Display name cannot be empty".to_string());
+    }
+    if profile.display_name.chars().count() > MAX_DISPLAY_NAME_LENGTH {
+        return Err(format!(
+            "Display name must be at most {} characters",
+            MAX_DISPLAY_NAME_LENGTH
+        ));
+    }
+    if profile.display_name.chars().any(char::is_control) {
+        return Err("Display name cannot contain control characters".to_string());
+    }
+    if profile.bio.chars().count() > MAX_BIO_LENGTH {
+        return Err(format!("Bio must be at most {} characters", MAX_BIO_LENGTH));
+    }
+    if let Some(avatar) = &profile.avatar {
+        validate_image_manifest(avatar, "Avatar")?;
+    }
+    if profile.links.len() > MAX_LINKS {
+        return Err(format!("Profiles can have at most {} links", MAX_LINKS));
+    }
+    for link in &profile.links {
+        validate_url(link)?;
+    }
+    Ok(())
+}
+
+/// Address of the first version of an agent's profile, if it has one.
+///
+/// Agents should only ever link one profile, but if there are several the
+/// earliest, by the time in the chain header of its first version, is used.
+/// Ties are broken by address.
+fn profile_address(agent: &Address) -> ZomeApiResult<Option<Address>> {
+    let profiles = api::get_links(agent, LinkMatch::Exactly("agent_profile"), LinkMatch::Any)?.addresses();
+    let mut first: Option<(i64, String)> = None;
+    for address in profiles {
+        let key = match created_at(&address)? {
+            Some(time) => (time, String::from(address)),
+            None => continue,
+        };
+        if first.as_ref().map_or(true, |first| &key < first) {
+            first = Some(key);
+        }
+    }
+    Ok(first.map(|(_, address)| Address::from(address)))
+}
+
+/// Set this agent's profile, replacing any previous one
+fn handle_set_profile(
+    display_name: String,
+    bio: String,
+    avatar: Option<Address>,
+    links: Vec<String>,
+    utc_unix_time: u64,
+) -> ZomeApiResult<Address> {
+    let profile = Entry::App(
+        "profile".into(),
+        Profile {
+            display_name: display_name.trim().to_owned(),
+            bio,
+            avatar,
+            links,
+            key_hash: api::AGENT_ADDRESS.clone(),
+            timestamp: utc_unix_time.into(),
+        }
+        .into(),
+    );
+    match profile_address(&api::AGENT_ADDRESS)? {
+        Some(address) => {
+            let latest = latest_address(&address)?
+                .ok_or_else(|| ZomeApiError::Internal("Profile not found".to_string()))?;
+            api::update_entry(profile, &latest)
+        }
+        None => {
+            let address = api::commit_entry(&profile)?;
+            api::link_entries(&api::AGENT_ADDRESS, &address, "agent_profile", "")?;
+            Ok(address)
+        }
+    }
+}
+
+/// Get the profile of an agent, falling back to its nick if it never set
+/// one
+fn handle_get_profile(agent: Address) -> ZomeApiResult<AgentProfile> {
+    if let Some(address) = profile_address(&agent)? {
+        if let Ok(profile) = utils::get_as_type::<Profile>(address) {
+            return Ok(AgentProfile {
+                agent,
+                display_name: profile.display_name,
+                bio: profile.bio,
+                avatar: profile.avatar,
+                links: profile.links,
+            });
+        }
+    }
+    match api::get_entry(&agent)? {
+        Some(Entry::AgentId(agent_id)) => Ok(AgentProfile {
+            agent,
+            display_name: agent_id.nick,
+            bio: String::new(),
+            avatar: None,
+            links: Vec::new(),
+        }),
+        _ => Err(ZomeApiError::Internal(
+            "Address did not lead to agent id.".to_string()
+        )),
+    }
+}
+
+/// Get the profiles of several agents at once, in the same order.
+///
+/// Each agent gets its own result, so one invalid agent does not hide the
+/// profiles of the others.
+fn handle_get_profiles(agents: Vec<Address>) -> ZomeApiResult<Vec<ZomeApiResult<AgentProfile>>> {
+    Ok(agents.into_iter().map(handle_get_profile).collect())
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "profile",
+            description: "Public profile of an agent",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<Profile>| {
+                let not_ok = Err("Cannot alter profile that is not yours.".to_string());
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: profile,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if provenances.iter().all(|provenance| provenance.0 == profile.key_hash) {
+                            validate_profile(&profile)?;
+                            validate_timestamp(&profile.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            not_ok
+                        }
+                    }
+                    EntryValidationData::Modify {
+                        new_entry: new_profile,
+                        old_entry: old_profile,
+                        old_entry_header,
+                        validation_data,
+                    } => {
+                        let mut provenances = validation_data.package.chain_header.provenances()
+                            .iter()
+                            .chain(old_entry_header.provenances());
+                        if old_profile.key_hash == new_profile.key_hash
+                            && provenances.all(|provenance| provenance.0 == old_profile.key_hash)
+                        {
+                            validate_profile(&new_profile)?;
+                            validate_timestamp(&new_profile.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            not_ok
+                        }
+                    }
+                    EntryValidationData::Delete { .. } => {
+                        Err("Profiles cannot be deleted, set a new one instead".to_string())
+                    }
+                }
+            },
+            links: [
+                // Profiles link from (to implicit by `key_hash` field) their agent's key hash
+                from!(
+                    "%agent_id",
+                    link_type: "agent_profile",
+                    validation_package: || hdk::ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if !provenances.iter().all(|provenance| &provenance.0 == link.link().base()) {
+                            return Err("Cannot link to profile from agent not in `key_hash`".to_owned());
+                        }
+                        match utils::get_as_type::<Profile>(link.link().target().clone()) {
+                            Ok(profile) => {
+                                if &profile.key_hash == link.link().base() {
+                                    Ok(())
+                                } else {
+                                    Err("Cannot link to profile from agent not in `key_hash`".to_owned())
+                                }
+                            },
+                            Err(_) => Err("Link was not profile".to_owned())
+                        }
+                    }
+                )
+            ]
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        set_profile: {
+            inputs: |display_name: String, bio: String, avatar: Option<Address>, links: Vec<String>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_set_profile
+        }
+        get_profile: {
+            inputs: |agent: Address|,
+            outputs: |result: ZomeApiResult<AgentProfile>|,
+            handler: handle_get_profile
+        }
+        get_profiles: {
+            inputs: |agents: Vec<Address>|,
+            outputs: |result: ZomeApiResult<Vec<ZomeApiResult<AgentProfile>>>|,
+            handler: handle_get_profiles
+        }
+    ]
+
+    traits: {
+        hc_public [
+            set_profile,
+            get_profile,
+            get_profiles
+        ]
+    }
+}
@@ -0,0 +1,204 @@
+#[macro_use]
+extern crate hdk;
+extern crate serde;
+#[macro_use]
+extern crate serde_derive;
+extern crate serde_json;
+#[macro_use]
+extern crate holochain_json_derive;
+extern crate comet_common;
+
+use hdk::api;
+use hdk::EntryValidationData;
+use hdk::error::{ZomeApiError, ZomeApiResult};
+use hdk::holochain_core_types::{
+    dna::entry_types::Sharing, entry::Entry,
+    time::Iso8601,
+};
+use hdk::holochain_persistence_api::cas::content::Address;
+use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
+use hdk::ValidationPackageDefinition;
+use comet_common::{newest_chain_entries, root_address, unix_time, validate_timestamp};
+use std::collections::HashMap;
+
+/// How many saved items are returned per page
+const PAGE_SIZE: usize = 25;
+
+/// Longest note that can be kept with a saved item, in characters
+const MAX_NOTE_LENGTH: usize = 1000;
+
+/// Saving or unsaving of a post or comment.
+///
+/// These entries are private, so they never leave the agent's chain. The
+/// latest one for a target says whether it is currently saved.
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct SavedItem {
+    /// Address of the first version of the saved post or comment
+    target: Address,
+    /// Entry type of `target`, either `post` or `comment`
+    target_type: String,
+    note: Option<String>,
+    /// `false` if this entry unsaves `target`
+    saved: bool,
+    timestamp: Iso8601,
+}
+
+/// A currently saved post or comment
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone, PartialEq)]
+struct Saved {
+    target: Address,
+    target_type: String,
+    note: Option<String>,
+    /// Time the item was saved, taken from its chain header
+    saved_at: Iso8601,
+}
+
+/// Returns `Ok(())` if a saved item is of a post or comment and its note is
+/// short enough
+fn validate_saved_item(saved_item: &SavedItem) -> Result<(), String> {
+    if saved_item.target_type != "post" && saved_item.target_type != "comment" {
+        return Err("Only posts and comments can be saved".to_string());
+    }
+    match &saved_item.note {
+        Some(note) if note.chars().count() > MAX_NOTE_LENGTH => Err(format!(
+            "Notes must be at most {} characters",
+            MAX_NOTE_LENGTH
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The latest saved item entry for every target this agent has saved or
+/// unsaved, along with the time it was committed
+fn latest_saved_items() -> ZomeApiResult<HashMap<Address, (SavedItem, Iso8601)>> {
+    let mut latest: HashMap<Address, (SavedItem, Iso8601)> = HashMap::new();
+    for (header, saved_item) in newest_chain_entries::<SavedItem>("saved_item")? {
+        latest
+            .entry(saved_item.target.clone())
+            .or_insert_with(|| (saved_item, header.timestamp().clone()));
+    }
+    Ok(latest)
+}
+
+/// Save a post or comment, along with an optional note
+///
+/// Saving an item that is already saved replaces its note. `address` can be
+/// the address of any version of the post or comment.
+fn handle_save(address: Address, note: Option<String>, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let target = root_address(&address)?;
+    let target_type = match api::get_entry(&target)? {
+        Some(Entry::App(entry_type, _)) => String::from(entry_type),
+        _ => return Err(ZomeApiError::Internal("Only posts and comments can be saved".to_string())),
+    };
+    let saved_item = SavedItem {
+        target,
+        target_type,
+        note,
+        saved: true,
+        timestamp: utc_unix_time.into(),
+    };
+    api::commit_entry(&Entry::App("saved_item".into(), saved_item.into()))
+}
+
+/// Unsave a saved post or comment
+fn handle_unsave(address: Address, utc_unix_time: u64) -> ZomeApiResult<Address> {
+    let target = root_address(&address)?;
+    let target_type = match latest_saved_items()?.remove(&target) {
+        Some((saved_item, _)) if saved_item.saved => saved_item.target_type,
+        _ => return Err(ZomeApiError::Internal("Item is not saved".to_string())),
+    };
+    let saved_item = SavedItem {
+        target,
+        target_type,
+        note: None,
+        saved: false,
+        timestamp: utc_unix_time.into(),
+    };
+    api::commit_entry(&Entry::App("saved_item".into(), saved_item.into()))
+}
+
+/// Get a page of this agent's saved posts and comments, most recently saved
+/// first. Pages start at 0 and hold `PAGE_SIZE` items.
+fn handle_saved_items(page: u32) -> ZomeApiResult<Vec<Saved>> {
+    let mut saved: Vec<(i64, Saved)> = Vec::new();
+    for (target, (saved_item, saved_at)) in latest_saved_items()? {
+        if saved_item.saved {
+            saved.push((
+                unix_time(&saved_at).map_err(ZomeApiError::Internal)?,
+                Saved {
+                    target,
+                    target_type: saved_item.target_type,
+                    note: saved_item.note,
+                    saved_at,
+                },
+            ));
+        }
+    }
+    saved.sort_by(|(a_time, a), (b_time, b)| {
+        b_time
+            .cmp(a_time)
+            .then_with(|| String::from(a.target.clone()).cmp(&String::from(b.target.clone())))
+    });
+    Ok(saved
+        .into_iter()
+        .map(|(_, saved)| saved)
+        .skip(page as usize * PAGE_SIZE)
+        .take(PAGE_SIZE)
+        .collect())
+}
+
+define_zome! {
+    entries: [
+        entry!(
+            name: "saved_item",
+            description: "Private record of saving or unsaving a post or comment",
+            sharing: Sharing::Private,
+
+            validation_package: || ValidationPackageDefinition::Entry,
+            validation: |entry_validation_data: hdk::EntryValidationData<SavedItem>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: saved_item,
+                        validation_data,
+                    } => {
+                        validate_saved_item(&saved_item)?;
+                        validate_timestamp(&saved_item.timestamp, &validation_data.package.chain_header)
+                    }
+                    _ => Err("Saved items cannot be changed, save or unsave again".to_string()),
+                }
+            }
+        )
+    ]
+
+    init: || { Ok(()) }
+
+    validate_agent: |validation_data: EntryValidationData::<AgentId>| {{
+        Ok(())
+    }}
+
+    functions: [
+        save: {
+            inputs: |address: Address, note: Option<String>, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_save
+        }
+        unsave: {
+            inputs: |address: Address, utc_unix_time: u64|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_unsave
+        }
+        saved_items: {
+            inputs: |page: u32|,
+            outputs: |result: ZomeApiResult<Vec<Saved>>|,
+            handler: handle_saved_items
+        }
+    ]
+
+    traits: {
+        hc_public [
+            save,
+            unsave,
+            saved_items
+        ]
+    }
+}
@@ -6,27 +6,96 @@ extern crate serde_derive;
 extern crate serde_json;
 #[macro_use]
 extern crate holochain_json_derive;
+extern crate comet_common;
 
 use hdk::api;
 use hdk::{EntryValidationData, LinkValidationData};
 use hdk::error::{ZomeApiError, ZomeApiResult};
 use hdk::holochain_core_types::{
-    dna::entry_types::Sharing, entry::Entry,
+    chain_header::ChainHeader, dna::entry_types::Sharing, entry::Entry,
     time::Iso8601, link::LinkMatch
 };
 use hdk::holochain_persistence_api::cas::content::Address;
 use hdk::holochain_json_api::{ json::JsonString, error::JsonError };
 use hdk::utils;
 use hdk::ValidationPackageDefinition;
-use holochain_wasm_utils::api_serialization::query::{
-    QueryArgsNames, QueryArgsOptions, QueryResult,
+use holochain_wasm_utils::api_serialization::{
+    get_entry::{GetEntryResultType, StatusRequestKind},
+    query::{QueryArgsNames, QueryArgsOptions, QueryResult},
 };
+use comet_common::{
+    blocked, live_chain_entries, options_with_headers, root_address, unix_time, validate_timestamp, Blockable,
+    CometProperties,
+};
+use std::collections::{HashMap, HashSet};
 
 /// Type for tags
 ///
 /// Should be exact same as in `posts` zome
 type Tag = u64;
 
+/// Anchor entry from anchors zome.
+///
+/// Should be exact same as in `anchors` zome
+#[derive(Serialize, Deserialize, Debug, DefaultJson, Clone)]
+struct Anchor {
+    anchor_type: String,
+    anchor_text: String,
+}
+
+/// Fractional value of a vote, stored in thousandths.
+///
+/// Fixed-point so that scores add up the same on every agent. `1000` is a
+/// full upvote and `-1000` is a full downvote.
+///
+/// Serialized as an integer. Votes cast before the move to fixed-point stored
+/// a float from 1 to -1; those still deserialize.
+#[derive(Clone, Copy, Serialize, Debug, PartialEq, Eq)]
+pub struct Fraction(i16);
+
+impl Fraction {
+    /// Number of units that make up a full vote
+    const ONE: i16 = 1000;
+
+    /// Convert a float from 1 to -1 into a `Fraction`, rounding to the nearest
+    /// thousandth. Values outside of the range of `Fraction` saturate.
+    ///
+    /// Returns `None` for NaN and infinite values.
+    fn from_f32(fraction: f32) -> Option<Fraction> {
+        if fraction.is_finite() {
+            Some(Fraction((fraction * f32::from(Fraction::ONE)).round() as i16))
+        } else {
+            None
+        }
+    }
+
+    /// Value of this fraction in thousandths
+    fn thousandths(self) -> i16 {
+        self.0
+    }
+
+    /// Whether this fraction is from 1 to -1 inclusive
+    fn in_range(self) -> bool {
+        self.0 <= Fraction::ONE && self.0 >= -Fraction::ONE
+    }
+}
+
+impl<'de> serde::Deserialize<'de> for Fraction {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum FractionRepr {
+            Thousandths(i16),
+            Legacy(f32),
+        }
+        match <FractionRepr as serde::Deserialize>::deserialize(deserializer)? {
+            FractionRepr::Thousandths(thousandths) => Ok(Fraction(thousandths)),
+            FractionRepr::Legacy(fraction) => Fraction::from_f32(fraction)
+                .ok_or_else(|| serde::de::Error::custom("Vote fraction must be a finite number")),
+        }
+    }
+}
+
 /// A user vote
 ///
 /// Can be "cast" (linked) from posts and comments.
@@ -36,7 +105,7 @@ pub struct Vote {
     ///
     /// - Positive = 'I like this'
     /// - Negative = 'I don't like this'
-    fraction: f32,
+    fraction: Fraction,
     /// What this vote has been cast in terms of
     ///
     /// e.g. a cat picture would be great (positive vote) on the tag
@@ -50,10 +119,54 @@ pub struct Vote {
     /// Time of the vote cast.
     /// They are used to avoid accidental hash collisions.
     ///
-    /// *Should not be used as real timestamp.*
+    /// Validated to be within `TIMESTAMP_TOLERANCE` of the chain header, but
+    /// `TimedVote.header_timestamp` should be preferred.
     timestamp: Iso8601,
 }
 
+/// Delegation of an agent's voting weight in a tag to another agent
+///
+/// When scoring, the vote of `delegate` also counts for `key_hash` (scaled by
+/// `weight`), unless `key_hash` voted themselves.
+#[derive(Clone, Serialize, Deserialize, Debug, DefaultJson, PartialEq)]
+pub struct Delegation {
+    /// Tag this delegation applies to
+    tag: Tag,
+    /// Who is receiving the voting weight
+    delegate: Address,
+    /// How much of `key_hash`'s voting weight is given, from 0 to 1
+    weight: Fraction,
+    /// Who is delegating their voting weight
+    key_hash: Address,
+    /// Time of the delegation.
+    /// They are used to avoid accidental hash collisions.
+    timestamp: Iso8601,
+}
+
+/// Weight of a single vote, in millionths
+const FULL_WEIGHT: i64 = 1_000_000;
+
+/// A vote along with the time it was cast and who cast it, taken from its
+/// chain header
+#[derive(Clone, Serialize, Deserialize, Debug, DefaultJson)]
+struct TimedVote {
+    vote: Vote,
+    /// Time the latest version of this vote was committed, taken from its
+    /// chain header rather than from `Vote.timestamp`
+    header_timestamp: Iso8601,
+    author: Address,
+}
+
+/// Score of a post or comment
+#[derive(Clone, Serialize, Deserialize, Debug, DefaultJson, PartialEq)]
+struct Score {
+    /// Sum of the (possibly decayed) fractions of all counted votes, in
+    /// thousandths
+    score: i64,
+    /// How many votes were counted
+    votes: u32,
+}
+
 /// Wrapper struct to get around the lack of implementation of
 /// `Into<JsonString>` for `T` where `T: Serialize`
 #[derive(Serialize)]
@@ -67,20 +180,38 @@ impl Into<JsonString> for PossibleVote {
 
 /// Create and link a vote on a target if one does not exist, otherwise update
 /// the current one to reflect the new fractional value requested
+///
+/// `fraction` is rounded to the nearest thousandth. `target` can be the
+/// address of any version of a post or comment.
 fn handle_vote(
     utc_unix_time: u64,
     fraction: f32,
     in_terms_of: Vec<Tag>,
     target: Address,
 ) -> ZomeApiResult<Address> {
+    let target = root_address(&target)?;
+    let fraction = Fraction::from_f32(fraction).ok_or_else(|| {
+        ZomeApiError::Internal("Vote fraction must be a finite number".to_string())
+    })?;
+    if !vote_tags_valid(&in_terms_of, &target_tags(&target)?) {
+        return Err(ZomeApiError::Internal(
+            "Vote must be in terms of the tags of its target".to_string(),
+        ));
+    }
     let vote = Vote {
         fraction,
-        in_terms_of: in_terms_of.clone(),
+        in_terms_of,
         target_hash: target.clone(),
         key_hash: api::AGENT_ADDRESS.clone(),
         timestamp: utc_unix_time.into(),
     };
+    handle_vote_raw(vote, target)
+}
 
+/// Cast a vote given a full `Vote` struct, including `timestamp`,
+/// `target_hash` and `key_hash`
+fn handle_vote_raw(vote: Vote, target: Address) -> ZomeApiResult<Address> {
+    let target = root_address(&target)?;
     let target_entry_type = match api::get_entry(&target)? {
         Some(Entry::App(entry_type, _)) => entry_type,
         _ => return Err(ZomeApiError::Internal("Vote target was not app entry.".to_string())),
@@ -92,7 +223,7 @@ fn handle_vote(
         _ => return Err(ZomeApiError::Internal("Vote target was not post or comment.".to_string())),
     };
 
-    if let Some(prev_vote) = find_my_vote(&target, &in_terms_of)? {
+    if let Some(prev_vote) = find_my_vote(&target, &vote.in_terms_of)? {
         api::update_entry(Entry::App("vote".into(), vote.into()), &prev_vote)
     } else {
         let result = api::commit_entry(&Entry::App("vote".into(), vote.into()))?;
@@ -103,14 +234,41 @@ fn handle_vote(
 }
 
 /// Get all votes linked from a specific address
-fn handle_votes_from_address(address: Address) -> ZomeApiResult<Vec<Vote>> {
-    Ok(api::get_links_and_load(&address, LinkMatch::Regex("\\w+?_vote"), LinkMatch::Any)?
+///
+/// Votes in terms of tags the target is not in are left out. When
+/// `apply_filters` is set, votes by agents blocked by the calling agent are
+/// left out too.
+fn handle_votes_from_address(address: Address, apply_filters: bool) -> ZomeApiResult<Vec<Vote>> {
+    let address = root_address(&address)?;
+    let tags = target_tags(&address).unwrap_or_default();
+    let votes: Vec<Vote> =
+        api::get_links_and_load(&address, LinkMatch::Regex("\\w+?_vote"), LinkMatch::Any)?
+            .into_iter()
+            .filter_map(|result| result.ok())
+            .filter_map(|entry| match entry {
+                Entry::App(_, value) => serde_json::from_str::<Vote>(&Into::<String>::into(value)).ok(),
+                _ => None,
+            })
+            .filter(|vote| vote_tags_valid(&vote.in_terms_of, &tags))
+            .collect();
+    if !apply_filters {
+        return Ok(votes);
+    }
+    let is_blocked = blocked(
+        votes
+            .iter()
+            .map(|vote| Blockable {
+                author: vote.key_hash.clone(),
+                tags: Vec::new(),
+                texts: Vec::new(),
+            })
+            .collect(),
+    )?;
+    Ok(votes
         .into_iter()
-        .filter_map(|result| result.ok())
-        .filter_map(|entry| match entry {
-            Entry::App(_, value) => serde_json::from_str::<Vote>(&Into::<String>::into(value)).ok(),
-            _ => None,
-        })
+        .zip(is_blocked)
+        .filter(|(_, is_blocked)| !is_blocked)
+        .map(|(vote, _)| vote)
         .collect())
 }
 
@@ -143,7 +301,7 @@ fn find_my_vote(address: &Address, in_terms_of: &[Tag]) -> ZomeApiResult<Option<
 /// Find and return the user's vote on some address in terms of `in_terms_of`,
 /// if it exists
 fn handle_get_my_vote(address: Address, in_terms_of: Vec<Tag>) -> ZomeApiResult<PossibleVote> {
-    let my_vote = find_my_vote(&address, &in_terms_of)?;
+    let my_vote = find_my_vote(&root_address(&address)?, &in_terms_of)?;
 
     if let Some(address) = my_vote {
         if let Some(entry) = api::get_entry(&address)? {
@@ -171,10 +329,530 @@ fn handle_get_my_vote(address: Address, in_terms_of: Vec<Tag>) -> ZomeApiResult<
     }
 }
 
+/// Get the tags that `address` can be voted on in terms of.
+///
+/// Posts can be voted on in terms of their original and crosspost tags.
+/// Comments can be voted on in terms of the tags of the post they were
+/// (eventually) made on, following at most `max_comment_depth` parents from
+/// the DNA properties.
+///
+/// Tags come from links on the DHT, so they are never checked in validation,
+/// where the result could differ between validators. Votes are checked
+/// against them when cast, and votes that fail are left out whenever votes
+/// are read or scored, so votes committed by a client that skips the check
+/// do not count.
+fn target_tags(address: &Address) -> ZomeApiResult<Vec<Tag>> {
+    fn get_tags(address: &Address, link_type: &str) -> ZomeApiResult<Vec<Tag>> {
+        Ok(api::get_links(address, LinkMatch::Exactly(link_type), LinkMatch::Any)?
+            .addresses()
+            .into_iter()
+            .filter_map(|address| match utils::get_as_type::<Anchor>(address) {
+                Ok(anchor) => serde_json::from_str::<Tag>(&anchor.anchor_text).ok(),
+                Err(_) => None,
+            })
+            .collect())
+    }
+
+    let max_depth = CometProperties::load().map_err(ZomeApiError::Internal)?.max_comment_depth;
+    let mut address = address.clone();
+    for _ in 0..=max_depth {
+        let entry_type = match api::get_entry(&address)? {
+            Some(Entry::App(entry_type, _)) => Into::<String>::into(entry_type),
+            _ => return Err(ZomeApiError::Internal("Vote target was not app entry.".to_string())),
+        };
+        match entry_type.as_ref() {
+            "post" => {
+                let mut tags = get_tags(&address, "post_to_original_tag")?;
+                tags.extend(get_tags(&address, "post_to_crosspost_tag")?);
+                return if tags.is_empty() {
+                    Err(ZomeApiError::Internal("Vote target has no tags".to_string()))
+                } else {
+                    Ok(tags)
+                };
+            }
+            "comment" => {
+                let parent = api::get_links(&address, LinkMatch::Regex("child_of_\\w+"), LinkMatch::Any)?
+                    .addresses()
+                    .into_iter()
+                    .next();
+                match parent {
+                    Some(parent) => address = parent,
+                    None => return Err(ZomeApiError::Internal(
+                        "Comment is not on a post".to_string(),
+                    )),
+                }
+            }
+            _ => return Err(ZomeApiError::Internal("Vote target was not post or comment.".to_string())),
+        }
+    }
+    Err(ZomeApiError::Internal("Comment is nested too deeply to find its post".to_string()))
+}
+
+/// Whether a vote in terms of `in_terms_of` only uses `tags`, the tags from
+/// `target_tags` of its target
+fn vote_tags_valid(in_terms_of: &[Tag], tags: &[Tag]) -> bool {
+    in_terms_of.iter().all(|tag| tags.contains(tag))
+}
+
+/// Returns `Ok(())` if the contents of a vote are valid, regardless of who
+/// cast it
+fn validate_vote(vote: &Vote) -> Result<(), String> {
+    if vote.fraction.in_range() {
+        Ok(())
+    } else {
+        Err("Vote fraction must be between 1 and -1".to_string())
+    }
+}
+
+/// Get the vote at `address` along with the time of its latest chain header
+fn get_timed_vote(address: &Address) -> ZomeApiResult<Option<TimedVote>> {
+    match api::get_entry_result(address, options_with_headers(StatusRequestKind::Latest))?.result {
+        GetEntryResultType::Single(item) => {
+            let vote = match item.entry {
+                Some(Entry::App(_, value)) => {
+                    match serde_json::from_str::<Vote>(&Into::<String>::into(value)) {
+                        Ok(vote) => vote,
+                        Err(_) => return Ok(None),
+                    }
+                }
+                _ => return Ok(None),
+            };
+            Ok(item.headers.first().map(|header| TimedVote {
+                author: header
+                    .provenances()
+                    .first()
+                    .map(|provenance| provenance.0.clone())
+                    .unwrap_or_else(|| vote.key_hash.clone()),
+                header_timestamp: header.timestamp().clone(),
+                vote,
+            }))
+        }
+        GetEntryResultType::All(_) => Err(ZomeApiError::Internal(
+            "Getting latest vote returned entry history".to_string(),
+        )),
+    }
+}
+
+/// Get all votes linked from a specific address, along with the time each
+/// one was cast. Votes in terms of tags the target is not in are left out.
+fn handle_timed_votes_from_address(address: Address) -> ZomeApiResult<Vec<TimedVote>> {
+    let address = root_address(&address)?;
+    let tags = target_tags(&address).unwrap_or_default();
+    Ok(api::get_links(&address, LinkMatch::Regex("\\w+?_vote"), LinkMatch::Any)?
+        .addresses()
+        .iter()
+        .filter_map(|address| get_timed_vote(address).ok().and_then(|vote| vote))
+        .filter(|timed_vote| vote_tags_valid(&timed_vote.vote.in_terms_of, &tags))
+        .collect())
+}
+
+/// Weight of a vote that is `age` seconds old, in millionths, halving every
+/// `half_life` seconds.
+///
+/// Interpolates linearly between halvings so that only integer math is used,
+/// keeping scores identical across agents.
+fn decay_weight(age: u64, half_life: u64) -> i64 {
+    if half_life == 0 {
+        return 0;
+    }
+    let halvings = age / half_life;
+    if halvings >= 20 {
+        return 0;
+    }
+    let weight = FULL_WEIGHT >> halvings;
+    let lost = (weight / 2) as u128 * (age % half_life) as u128 / half_life as u128;
+    weight - lost as i64
+}
+
+/// Score a post or comment by summing the votes on it in terms of any of
+/// `in_terms_of` (or all votes if `in_terms_of` is empty).
+///
+/// Each vote also counts for the agents that delegated to its voter in the
+/// tags it was counted in terms of.
+///
+/// When `half_life` is given, each vote counts half as much for every
+/// `half_life` seconds between when it was cast and `utc_unix_time`. Vote
+/// times come from chain headers, not `Vote.timestamp`.
+fn handle_score(
+    address: Address,
+    in_terms_of: Vec<Tag>,
+    utc_unix_time: u64,
+    half_life: Option<u64>,
+) -> ZomeApiResult<Score> {
+    score_votes(handle_timed_votes_from_address(address)?, &in_terms_of, utc_unix_time, half_life, &HashMap::new())
+}
+
+/// Score `timed_votes` like `handle_score` does, with each vote's weight
+/// also scaled by its voter's weight in `trust`, in millionths. Voters not
+/// in `trust` count fully.
+fn score_votes(
+    timed_votes: Vec<TimedVote>,
+    in_terms_of: &[Tag],
+    utc_unix_time: u64,
+    half_life: Option<u64>,
+    trust: &HashMap<Address, i64>,
+) -> ZomeApiResult<Score> {
+    let counted_tags = |vote: &Vote| -> Vec<Tag> {
+        vote.in_terms_of
+            .iter()
+            .cloned()
+            .filter(|tag| in_terms_of.is_empty() || in_terms_of.contains(tag))
+            .collect()
+    };
+    let timed_votes: Vec<TimedVote> = timed_votes
+        .into_iter()
+        .filter(|timed_vote| !counted_tags(&timed_vote.vote).is_empty())
+        .collect();
+    let mut counted: HashSet<Address> = timed_votes
+        .iter()
+        .map(|timed_vote| timed_vote.vote.key_hash.clone())
+        .collect();
+
+    let max_depth = CometProperties::load().map_err(ZomeApiError::Internal)?.max_delegation_depth;
+
+    let mut score = Score { score: 0, votes: 0 };
+    for timed_vote in timed_votes {
+        let vote = timed_vote.vote;
+        let mut weight =
+            FULL_WEIGHT + delegated_weight(&vote.key_hash, &counted_tags(&vote), max_depth, &mut counted)?;
+        if let Some(half_life) = half_life {
+            let cast_at = unix_time(&timed_vote.header_timestamp).map_err(ZomeApiError::Internal)?;
+            let age = (utc_unix_time as i64).saturating_sub(cast_at).max(0) as u64;
+            weight = weight * decay_weight(age, half_life) / FULL_WEIGHT;
+        }
+        weight = weight * trust.get(&vote.key_hash).cloned().unwrap_or(FULL_WEIGHT) / FULL_WEIGHT;
+        score.score += i64::from(vote.fraction.thousandths()) * weight / FULL_WEIGHT;
+        score.votes += 1;
+    }
+    Ok(score)
+}
+
+/// How much the calling agent trusts `agent`, from the calling agent's votes
+/// on `agent`'s posts and comments
+#[derive(Clone, Serialize, Deserialize, Debug, DefaultJson, PartialEq)]
+struct Trust {
+    agent: Address,
+    /// Weight of `agent`'s opinion, in millionths. `FULL_WEIGHT` for agents
+    /// the calling agent has not voted on, up to twice that for agents it has
+    /// only upvoted and down to nothing for agents it has only downvoted.
+    weight: i64,
+}
+
+/// Latest version of each of the calling agent's votes
+fn my_latest_votes() -> ZomeApiResult<Vec<Vote>> {
+    let addresses = match api::query_result(
+        QueryArgsNames::QueryName("vote".to_string()),
+        QueryArgsOptions {
+            start: 0,
+            limit: usize::max_value(),
+            headers: false,
+            entries: false,
+        },
+    )? {
+        QueryResult::Addresses(addresses) => addresses,
+        _ => unreachable!(),
+    };
+
+    let mut seen = HashSet::new();
+    let mut votes = Vec::new();
+    for address in addresses {
+        if let GetEntryResultType::Single(item) =
+            api::get_entry_result(&address, options_with_headers(StatusRequestKind::Latest))?.result
+        {
+            if let (Some(meta), Some(Entry::App(_, value))) = (item.meta, item.entry) {
+                if seen.insert(meta.address) {
+                    if let Ok(vote) = serde_json::from_str::<Vote>(&Into::<String>::into(value)) {
+                        votes.push(vote);
+                    }
+                }
+            }
+        }
+    }
+    Ok(votes)
+}
+
+/// Author of the post or comment at `address`, as recorded in its `key_hash`
+fn target_author(address: &Address) -> ZomeApiResult<Option<Address>> {
+    Ok(match api::get_entry(address)? {
+        Some(Entry::App(_, value)) => serde_json::from_str::<serde_json::Value>(&Into::<String>::into(value))
+            .ok()
+            .and_then(|value| value["key_hash"].as_str().map(Address::from)),
+        _ => None,
+    })
+}
+
+/// How much the calling agent trusts each of `agents`.
+///
+/// Every vote the calling agent has cast on an agent's posts and comments
+/// moves that agent's weight away from `FULL_WEIGHT`, one full vote being
+/// enough to double it or take it away entirely.
+fn handle_trust(agents: Vec<Address>) -> ZomeApiResult<Vec<Trust>> {
+    let mut totals: Vec<i64> = vec![0; agents.len()];
+    for vote in my_latest_votes()? {
+        if let Some(author) = target_author(&vote.target_hash)? {
+            if let Some(index) = agents.iter().position(|agent| agent == &author) {
+                totals[index] += i64::from(vote.fraction.thousandths());
+            }
+        }
+    }
+    let one = i64::from(Fraction::ONE);
+    Ok(agents
+        .into_iter()
+        .zip(totals)
+        .map(|(agent, total)| Trust {
+            agent,
+            weight: FULL_WEIGHT + FULL_WEIGHT * total.max(-one).min(one) / one,
+        })
+        .collect())
+}
+
+/// How many posts and comments an agent has made, and how they were voted
+/// on
+#[derive(Clone, Serialize, Deserialize, Debug, DefaultJson, PartialEq)]
+struct UserStats {
+    posts: u32,
+    comments: u32,
+    /// How many votes were counted in the scores of the agent's posts and
+    /// comments
+    votes: u32,
+    /// Sum of the scores of the agent's posts and comments, as given by
+    /// `score` in terms of every tag and without decay, with each vote also
+    /// weighted by how much the calling agent trusts its voter
+    score: i64,
+}
+
+/// Get the stats of `agent` from the calling agent's perspective.
+///
+/// Votes are scored by `score`, so delegations count the same way, and each
+/// is then weighted by the `trust` the calling agent has in its voter.
+/// Delegated weight is scaled by the trust in the voter it was delegated to.
+fn handle_user_stats(agent: Address) -> ZomeApiResult<UserStats> {
+    let authored = |link_type: &str| -> ZomeApiResult<Vec<Address>> {
+        let addresses: HashSet<Address> =
+            api::get_links(&agent, LinkMatch::Exactly(link_type), LinkMatch::Any)?.addresses().into_iter().collect();
+        Ok(addresses.into_iter().collect())
+    };
+    let posts = authored("post_author")?;
+    let comments = authored("comment_author")?;
+    let mut timed_votes = Vec::new();
+    for address in posts.iter().chain(comments.iter()) {
+        timed_votes.push(handle_timed_votes_from_address(address.clone())?);
+    }
+    let voters: HashSet<Address> = timed_votes
+        .iter()
+        .flatten()
+        .map(|timed_vote| timed_vote.vote.key_hash.clone())
+        .collect();
+    let trust: HashMap<Address, i64> = handle_trust(voters.into_iter().collect())?
+        .into_iter()
+        .map(|trust| (trust.agent, trust.weight))
+        .collect();
+
+    let mut stats = UserStats {
+        posts: posts.len() as u32,
+        comments: comments.len() as u32,
+        votes: 0,
+        score: 0,
+    };
+    for votes in timed_votes {
+        let score = score_votes(votes, &[], 0, None, &trust)?;
+        stats.votes += score.votes;
+        stats.score += score.score;
+    }
+    Ok(stats)
+}
+
+/// Get the delegations linked from `agent` by `link_type`, along with their
+/// addresses
+fn get_delegations(agent: &Address, link_type: &str) -> ZomeApiResult<Vec<(Address, Delegation)>> {
+    Ok(api::get_links(agent, LinkMatch::Exactly(link_type), LinkMatch::Any)?
+        .addresses()
+        .into_iter()
+        .filter_map(|address| {
+            utils::get_as_type::<Delegation>(address.clone())
+                .ok()
+                .map(|delegation| (address, delegation))
+        })
+        .collect())
+}
+
+/// Total weight, in millionths, delegated to `agent` in any of `tags`, either
+/// directly or through a chain of at most `max_depth` delegations.
+///
+/// Delegations from agents in `counted` are not followed. It starts out as
+/// the voters, since their own votes count instead, and every delegator
+/// whose weight is added is put in it. Sharing it between all the votes of a
+/// score counts every agent at most once, which also stops cycles.
+fn delegated_weight(
+    agent: &Address,
+    tags: &[Tag],
+    max_depth: usize,
+    counted: &mut HashSet<Address>,
+) -> ZomeApiResult<i64> {
+    counted.insert(agent.clone());
+    let mut frontier = vec![(agent.clone(), FULL_WEIGHT)];
+    let mut total = 0;
+    for _ in 0..max_depth {
+        let mut next_frontier = Vec::new();
+        for (delegate, weight) in frontier {
+            for (_, delegation) in get_delegations(&delegate, "delegation_to")? {
+                if delegation.delegate != delegate
+                    || !tags.contains(&delegation.tag)
+                    || counted.contains(&delegation.key_hash)
+                {
+                    continue;
+                }
+                counted.insert(delegation.key_hash.clone());
+                let delegated =
+                    weight * i64::from(delegation.weight.thousandths()) / i64::from(Fraction::ONE);
+                total += delegated;
+                next_frontier.push((delegation.key_hash, delegated));
+            }
+        }
+        frontier = next_frontier;
+    }
+    Ok(total)
+}
+
+/// Delegate voting weight in `tag` to `agent`, replacing any delegation
+/// already made in that tag
+///
+/// `weight` is rounded to the nearest thousandth.
+fn handle_delegate(
+    utc_unix_time: u64,
+    tag: Tag,
+    agent: Address,
+    weight: f32,
+) -> ZomeApiResult<Address> {
+    let weight = Fraction::from_f32(weight).ok_or_else(|| {
+        ZomeApiError::Internal("Delegation weight must be a finite number".to_string())
+    })?;
+    let delegation = Delegation {
+        tag,
+        delegate: agent.clone(),
+        weight,
+        key_hash: api::AGENT_ADDRESS.clone(),
+        timestamp: utc_unix_time.into(),
+    };
+    let (replaced, others): (Vec<_>, Vec<_>) = get_delegations(&api::AGENT_ADDRESS, "delegation_from")?
+        .into_iter()
+        .partition(|(_, old_delegation)| old_delegation.tag == tag);
+    // Checked before the old delegation is removed, so it is kept if the new
+    // one would be rejected
+    let others: Vec<Delegation> = others.into_iter().map(|(_, delegation)| delegation).collect();
+    validate_outgoing_delegations(&delegation, &others).map_err(ZomeApiError::Internal)?;
+    for (address, old_delegation) in replaced {
+        remove_delegation(&address, &old_delegation)?;
+    }
+    let address = api::commit_entry(&Entry::App("delegation".into(), delegation.into()))?;
+    api::link_entries(&api::AGENT_ADDRESS, &address, "delegation_from", "")?;
+    api::link_entries(&agent, &address, "delegation_to", "")?;
+    Ok(address)
+}
+
+/// Unlink and remove a delegation
+fn remove_delegation(address: &Address, delegation: &Delegation) -> ZomeApiResult<Address> {
+    api::remove_link(&delegation.key_hash, address, "delegation_from", "")?;
+    api::remove_link(&delegation.delegate, address, "delegation_to", "")?;
+    api::remove_entry(address)
+}
+
+/// Unlink and remove the delegation at `address`, without checking that this
+/// agent made it
+fn handle_revoke_delegation_raw(address: Address) -> ZomeApiResult<Address> {
+    let delegation: Delegation = utils::get_as_type(address.clone())?;
+    remove_delegation(&address, &delegation)
+}
+
+/// Revoke the delegation made in `tag`
+fn handle_revoke_delegation(tag: Tag) -> ZomeApiResult<Address> {
+    match get_delegations(&api::AGENT_ADDRESS, "delegation_from")?
+        .into_iter()
+        .find(|(_, delegation)| delegation.tag == tag)
+    {
+        Some((address, delegation)) => remove_delegation(&address, &delegation),
+        None => Err(ZomeApiError::Internal("No delegation in that tag".to_string())),
+    }
+}
+
+/// Get the delegations made by `agent`
+fn handle_delegations_from(agent: Address) -> ZomeApiResult<Vec<Delegation>> {
+    Ok(get_delegations(&agent, "delegation_from")?
+        .into_iter()
+        .map(|(_, delegation)| delegation)
+        .collect())
+}
+
+/// Get the delegations made to `agent`
+fn handle_delegations_to(agent: Address) -> ZomeApiResult<Vec<Delegation>> {
+    Ok(get_delegations(&agent, "delegation_to")?
+        .into_iter()
+        .map(|(_, delegation)| delegation)
+        .filter(|delegation| delegation.delegate == agent)
+        .collect())
+}
+
+/// Returns `Ok(())` if the contents of a delegation are valid, regardless of
+/// who made it
+fn validate_delegation(delegation: &Delegation) -> Result<(), String> {
+    if delegation.weight.thousandths() < 0 || !delegation.weight.in_range() {
+        Err("Delegation weight must be between 0 and 1".to_string())
+    } else if delegation.delegate == delegation.key_hash {
+        Err("Cannot delegate to yourself".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if `delegation` can be made alongside `others`, the
+/// other live delegations of its delegator: there can only be one delegation
+/// per tag, and together they cannot delegate more than `FULL_WEIGHT`
+fn validate_outgoing_delegations(delegation: &Delegation, others: &[Delegation]) -> Result<(), String> {
+    if others.iter().any(|other| other.tag == delegation.tag) {
+        return Err("Already delegating in that tag".to_string());
+    }
+    let total: i64 = others
+        .iter()
+        .chain(std::iter::once(delegation))
+        .map(|delegation| i64::from(delegation.weight.thousandths()) * FULL_WEIGHT / i64::from(Fraction::ONE))
+        .sum();
+    if total > FULL_WEIGHT {
+        Err("Cannot delegate more than your full weight in total".to_string())
+    } else {
+        Ok(())
+    }
+}
+
+/// Returns `Ok(())` if `agent` made (when `from` is true) or received (when
+/// `from` is false) the delegation at `delegation_address`, and the link was
+/// added or removed (going by `header`) by the agent who made it
+fn validate_delegation_link(
+    agent: &Address,
+    delegation_address: &Address,
+    from: bool,
+    header: &ChainHeader,
+) -> Result<(), String> {
+    let delegation: Delegation = utils::get_as_type(delegation_address.clone())
+        .map_err(|_| "Failed to get delegation for link validation".to_string())?;
+    if !header.provenances().iter().all(|provenance| provenance.0 == delegation.key_hash) {
+        return Err("Delegations can only be linked and unlinked by their delegator".to_string());
+    }
+    let expected = if from { &delegation.key_hash } else { &delegation.delegate };
+    if expected == agent {
+        Ok(())
+    } else {
+        Err("Delegation must be linked from its delegator and delegate".to_string())
+    }
+}
+
 fn validate_vote_link(from: &Address, to: &Address) -> Result<(), String> {
     let vote: Vote =
         utils::get_as_type(to.clone()).map_err(|_| "Failed to get vote for link validation".to_string())?;
 
+    if &vote.target_hash != from {
+        return Err("Vote must be linked from its target".to_string());
+    }
+
     fn get_votes(exclude: &Vote, target_hash: &Address) -> Result<Vec<Vote>, String> {
         let all_votes = api::query_result(
             QueryArgsNames::QueryName("vote".to_string()),
@@ -241,11 +919,8 @@ define_zome! {
                     } => {
                         let provenances = validation_data.package.chain_header.provenances();
                         if provenances.iter().all(|provenance| provenance.0 == vote.key_hash) {
-                            if vote.fraction <= 1.0 && vote.fraction >= -1.0 {
-                                Ok(())
-                            } else {
-                                Err("Vote fraction must be between 1 and -1".to_string())
-                            }
+                            validate_vote(&vote)?;
+                            validate_timestamp(&vote.timestamp, &validation_data.package.chain_header)
                         } else {
                             not_ok
                         }
@@ -262,10 +937,11 @@ define_zome! {
                         if old_vote.key_hash == new_vote.key_hash
                             && provenances.all(|provenance| provenance.0 == old_vote.key_hash)
                         {
-                            if new_vote.fraction <= 1.0 && new_vote.fraction >= -1.0 {
-                                Ok(())
+                            if old_vote.target_hash == new_vote.target_hash {
+                                validate_vote(&new_vote)?;
+                                validate_timestamp(&new_vote.timestamp, &validation_data.package.chain_header)
                             } else {
-                                Err("Vote fraction must be between 1 and -1".to_string())
+                                Err("Cannot change the target of a vote".to_string())
                             }
                         } else {
                             not_ok
@@ -326,6 +1002,106 @@ define_zome! {
                     }
                 )
             ]
+        ),
+        entry!(
+            name: "delegation",
+            description: "Delegation of voting weight in a tag to another agent",
+            sharing: Sharing::Public,
+
+            validation_package: || ValidationPackageDefinition::ChainEntries,
+            validation: |entry_validation_data: hdk::EntryValidationData<Delegation>| {
+                match entry_validation_data {
+                    EntryValidationData::Create {
+                        entry: delegation,
+                        validation_data,
+                    } => {
+                        let provenances = validation_data.package.chain_header.provenances();
+                        if provenances.iter().all(|provenance| provenance.0 == delegation.key_hash) {
+                            validate_delegation(&delegation)?;
+                            let others: Vec<Delegation> = live_chain_entries::<Delegation>(
+                                &validation_data.package.source_chain_entries,
+                                "delegation",
+                            )?
+                            .into_iter()
+                            .map(|(_, other)| other)
+                            .filter(|other| other != &delegation && other.key_hash == delegation.key_hash)
+                            .collect();
+                            validate_outgoing_delegations(&delegation, &others)?;
+                            validate_timestamp(&delegation.timestamp, &validation_data.package.chain_header)
+                        } else {
+                            Err("Cannot delegate for somebody else.".to_string())
+                        }
+                    }
+                    EntryValidationData::Modify { .. } => {
+                        Err("Delegations cannot be updated, revoke and delegate again".to_string())
+                    }
+                    EntryValidationData::Delete {
+                        old_entry: old_delegation,
+                        old_entry_header,
+                        validation_data,
+                    } => {
+                        let mut provenances = validation_data.package.chain_header.provenances()
+                            .iter()
+                            .chain(old_entry_header.provenances());
+                        if provenances.all(|provenance| provenance.0 == old_delegation.key_hash)
+                        {
+                            Ok(())
+                        } else {
+                            Err("Cannot revoke delegation that is not yours.".to_string())
+                        }
+                    }
+                }
+            },
+            links: [
+                // Delegations link from the agent giving and the agent
+                // receiving voting weight
+                from!(
+                    "%agent_id",
+                    link_type: "delegation_from",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        validate_delegation_link(
+                            link.link().base(),
+                            link.link().target(),
+                            true,
+                            &validation_data.package.chain_header,
+                        )
+                    }
+                ),
+                from!(
+                    "%agent_id",
+                    link_type: "delegation_to",
+                    validation_package: || ValidationPackageDefinition::Entry,
+                    validation: |link_validation_data: hdk::LinkValidationData| {
+                        let (link, validation_data) = match link_validation_data {
+                            LinkValidationData::LinkAdd {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                            LinkValidationData::LinkRemove {
+                                link,
+                                validation_data,
+                            } => (link, validation_data),
+                        };
+                        validate_delegation_link(
+                            link.link().base(),
+                            link.link().target(),
+                            false,
+                            &validation_data.package.chain_header,
+                        )
+                    }
+                )
+            ]
         )
     ]
 
@@ -341,11 +1117,61 @@ define_zome! {
             outputs: |result: ZomeApiResult<Address>|,
             handler: handle_vote
         }
+        vote_raw: {
+            inputs: |vote: Vote, target: Address|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_vote_raw
+        }
         votes_from_address: {
-            inputs: |address: Address|,
+            inputs: |address: Address, apply_filters: bool|,
             outputs: |result: ZomeApiResult<Vec<Vote>>|,
             handler: handle_votes_from_address
         }
+        timed_votes_from_address: {
+            inputs: |address: Address|,
+            outputs: |result: ZomeApiResult<Vec<TimedVote>>|,
+            handler: handle_timed_votes_from_address
+        }
+        score: {
+            inputs: |address: Address, in_terms_of: Vec<Tag>, utc_unix_time: u64, half_life: Option<u64>|,
+            outputs: |result: ZomeApiResult<Score>|,
+            handler: handle_score
+        }
+        trust: {
+            inputs: |agents: Vec<Address>|,
+            outputs: |result: ZomeApiResult<Vec<Trust>>|,
+            handler: handle_trust
+        }
+        user_stats: {
+            inputs: |agent: Address|,
+            outputs: |result: ZomeApiResult<UserStats>|,
+            handler: handle_user_stats
+        }
+        delegate: {
+            inputs: |utc_unix_time: u64, tag: Tag, agent: Address, weight: f32|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_delegate
+        }
+        revoke_delegation: {
+            inputs: |tag: Tag|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_revoke_delegation
+        }
+        revoke_delegation_raw: {
+            inputs: |address: Address|,
+            outputs: |result: ZomeApiResult<Address>|,
+            handler: handle_revoke_delegation_raw
+        }
+        delegations_from: {
+            inputs: |agent: Address|,
+            outputs: |result: ZomeApiResult<Vec<Delegation>>|,
+            handler: handle_delegations_from
+        }
+        delegations_to: {
+            inputs: |agent: Address|,
+            outputs: |result: ZomeApiResult<Vec<Delegation>>|,
+            handler: handle_delegations_to
+        }
         get_my_vote: {
             inputs: |address: Address, in_terms_of: Vec<Tag>|,
             outputs: |result: ZomeApiResult<PossibleVote>|,
@@ -356,7 +1182,17 @@ define_zome! {
     traits: {
         hc_public [
             vote,
+            vote_raw,
             votes_from_address,
+            timed_votes_from_address,
+            score,
+            trust,
+            user_stats,
+            delegate,
+            revoke_delegation,
+            revoke_delegation_raw,
+            delegations_from,
+            delegations_to,
             get_my_vote
         ]
     }